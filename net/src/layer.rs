@@ -36,12 +36,12 @@ impl Layer {
         Self::new(neurons)
     }
 
-    pub fn mutate(&self, mutation_ratio: f64) -> Self {
+    pub fn mutate(&self, mutation_ratio: f64, rng: &mut dyn RngCore) -> Self {
         Self {
             neurons: self
                 .neurons
                 .iter()
-                .map(|x| x.mutate(mutation_ratio))
+                .map(|x| x.mutate(mutation_ratio, rng))
                 .collect(),
         }
     }
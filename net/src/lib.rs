@@ -4,7 +4,7 @@
 pub use self::layer_topology::*;
 
 use self::{layer::*, neuron::*};
-use rand::{prelude::ThreadRng, Rng, RngCore};
+use rand::{Rng, RngCore};
 use std::iter::once;
 
 mod layer;
@@ -30,7 +30,7 @@ impl Network {
         serde_json::from_str(s).unwrap()
     }
 
-    pub fn random(rng: &mut ThreadRng, layers: &[LayerTopology]) -> Self {
+    pub fn random(rng: &mut dyn RngCore, layers: &[LayerTopology]) -> Self {
         assert!(layers.len() > 1);
 
         let layers = layers
@@ -64,12 +64,12 @@ impl Network {
             .fold(inputs, |inputs, layer| layer.propagate(inputs))
     }
 
-    pub fn mutate(&self, mutation_ratio: f64) -> Self {
+    pub fn mutate(&self, mutation_ratio: f64, rng: &mut dyn RngCore) -> Self {
         Self {
             layers: self
                 .layers
                 .iter()
-                .map(|x| x.mutate(mutation_ratio))
+                .map(|x| x.mutate(mutation_ratio, rng))
                 .collect(),
         }
     }
@@ -45,10 +45,10 @@ impl Neuron {
         (self.bias + output).max(0.0)
     }
 
-    pub fn mutate(&self, mutation_ratio: f64) -> Self {
+    pub fn mutate(&self, mutation_ratio: f64, rng: &mut dyn RngCore) -> Self {
         Self {
-            bias: if rand::thread_rng().gen_bool(mutation_ratio) {
-                rand::thread_rng().gen_range(-1.0..=1.0)
+            bias: if rng.gen_bool(mutation_ratio) {
+                rng.gen_range(-1.0..=1.0)
             } else {
                 self.bias
             },
@@ -56,8 +56,8 @@ impl Neuron {
                 .weights
                 .iter()
                 .map(|x| {
-                    if rand::thread_rng().gen_bool(mutation_ratio) {
-                        rand::thread_rng().gen_range(-1.0..=1.0)
+                    if rng.gen_bool(mutation_ratio) {
+                        rng.gen_range(-1.0..=1.0)
                     } else {
                         *x
                     }
@@ -0,0 +1,108 @@
+use crate::{
+    genetic_builder::Bot,
+    grid::{CaseValue, Grid, GridProvider},
+    heuristic::HeuristicParams,
+    pns::ProofSearch,
+    search::{is_decisive, plays_to_end, Evaluator, Searcher},
+};
+
+#[derive(Clone, Debug)]
+pub struct MoveAnalysis {
+    pub index: usize,
+    // `evaluate_play`, the heuristic plays the lowest
+    pub heuristic: f32,
+    // `Bot::execute` after the play, the bot plays the lowest. Only for 5x6 grids.
+    pub network: Option<f32>,
+    // Search score for the side to move and the principal variation starting with the play
+    pub score: f32,
+    pub pv: Vec<usize>,
+    // Upper bound on the number of plays until the loss when the opponent can force a win
+    pub forced_loss: Option<u32>,
+}
+
+// Every legal play scored by the heuristic, the network and the search, the best search score
+// first
+pub fn analyse<T: GridProvider + Clone, E: Evaluator + Sync>(
+    grid: &Grid<T>,
+    params: &HeuristicParams,
+    bot: &Bot,
+    searcher: &mut Searcher<E>,
+    proof_search: Option<ProofSearch>,
+) -> Vec<MoveAnalysis> {
+    if grid.is_over() {
+        return Vec::new();
+    }
+    let color = if grid.is_red_turn() {
+        CaseValue::Red
+    } else {
+        CaseValue::Blue
+    };
+    let losing = proof_search
+        .map(|x| x.losing_moves(grid))
+        .unwrap_or_default();
+    let mut after = grid.clone();
+    let mut analysis = searcher
+        .search_moves(grid)
+        .into_iter()
+        .map(|(index, score, pv)| {
+            after.play(index).unwrap();
+            let network = if grid.width() * grid.height() == 5 * 6 {
+                Some(bot.execute(&after, color))
+            } else {
+                None
+            };
+            after.undo();
+            MoveAnalysis {
+                index,
+                heuristic: grid.evaluate_play(index, color, params),
+                network,
+                score,
+                pv,
+                forced_loss: losing.iter().find(|x| x.0 == index).map(|x| x.1),
+            }
+        })
+        .collect::<Vec<_>>();
+    analysis.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+    analysis
+}
+
+// One line per play with coordinates as typed to play, `played` is marked with a star
+pub fn table<T: GridProvider + Clone>(
+    grid: &Grid<T>,
+    analysis: &[MoveAnalysis],
+    played: Option<usize>,
+) -> String {
+    let coordinates = |index: usize| {
+        let (x, y) = grid.index_to_x_y(index);
+        format!("{} {}", x, y)
+    };
+    let mut table = format!(
+        "{:>7} {:>9} {:>9} {:>8}  PV",
+        "Play", "Heuristic", "Network", "Search"
+    );
+    for x in analysis {
+        let network = x.network.map_or("-".to_owned(), |x| format!("{:.3}", x));
+        let score = if is_decisive(x.score) {
+            let result = if x.score > 0. { "win" } else { "loss" };
+            format!("{} {}", result, plays_to_end(x.score))
+        } else {
+            format!("{:.3}", x.score)
+        };
+        table += &format!(
+            "\n{}{:>6} {:>9.3} {:>9} {:>8}  {}",
+            if Some(x.index) == played { "*" } else { " " },
+            coordinates(x.index),
+            x.heuristic,
+            network,
+            score,
+            x.pv.iter()
+                .map(|x| coordinates(*x))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        if let Some(plays) = x.forced_loss {
+            table += &format!(" forced loss in at most {}", plays);
+        }
+    }
+    table
+}
@@ -0,0 +1,218 @@
+use crate::grid::{CaseValue, Direction, GridProvider};
+
+// Cases are stored row by row with one padding column on the right of every row, so shifting a
+// mask horizontally or diagonally never wraps to another row.
+#[derive(Clone)]
+pub struct BitboardProvider {
+    width: usize,
+    height: usize,
+    board: u128,
+    red: u128,
+    blue: u128,
+    wall: u128,
+    unlocked: u128,
+}
+
+impl BitboardProvider {
+    pub fn fits(width: usize, height: usize) -> bool {
+        width > 0 && height > 0 && (width + 1) * height <= 128
+    }
+
+    pub fn new(width: usize, height: usize) -> Self {
+        assert!(
+            Self::fits(width, height),
+            "A {}x{} grid does not fit in a bitboard",
+            width,
+            height
+        );
+        let row = (1u128 << width) - 1;
+        let board = (0..height).fold(0, |board, y| board | row << (y * (width + 1)));
+        Self {
+            width,
+            height,
+            board,
+            red: 0,
+            blue: 0,
+            wall: 0,
+            unlocked: board,
+        }
+    }
+
+    fn stride(&self) -> usize {
+        self.width + 1
+    }
+
+    // Every row has one more bit than cases, so the position is the index plus its row
+    fn bit(&self, index: usize) -> u128 {
+        1 << (index + index / self.width)
+    }
+
+    // Case index of a bit position
+    fn index(&self, position: usize) -> usize {
+        position - position / self.stride()
+    }
+
+    fn shift(&self, mask: u128, dir: Direction) -> u128 {
+        let stride = self.stride();
+        let shifted = match dir {
+            Direction::North => shr(mask, stride),
+            Direction::South => shl(mask, stride),
+            Direction::East => shl(mask, 1),
+            Direction::West => shr(mask, 1),
+            Direction::NorthEast => shr(mask, stride - 1),
+            Direction::NorthWest => shr(mask, stride + 1),
+            Direction::SouthEast => shl(mask, stride + 1),
+            Direction::SouthWest => shl(mask, stride - 1),
+        };
+        shifted & self.board
+    }
+
+    fn mask(&self, value: CaseValue) -> u128 {
+        match value {
+            CaseValue::Red => self.red,
+            CaseValue::Blue => self.blue,
+            CaseValue::Black => self.wall,
+            CaseValue::Yellow => self.unlocked,
+            CaseValue::White => {
+                self.board & !(self.red | self.blue | self.wall | self.unlocked)
+            }
+        }
+    }
+
+    fn set_mask(&mut self, cases: u128, value: CaseValue) {
+        self.red &= !cases;
+        self.blue &= !cases;
+        self.wall &= !cases;
+        self.unlocked &= !cases;
+        match value {
+            CaseValue::Red => self.red |= cases,
+            CaseValue::Blue => self.blue |= cases,
+            CaseValue::Black => self.wall |= cases,
+            CaseValue::Yellow => self.unlocked |= cases,
+            CaseValue::White => (),
+        }
+    }
+}
+
+impl GridProvider for BitboardProvider {
+    fn get(&self, index: usize) -> Option<CaseValue> {
+        if index < self.width * self.height {
+            // Perfectly safe since the index is in the grid.
+            Some(unsafe { self.get_unchecked(index) })
+        } else {
+            None
+        }
+    }
+
+    fn set(&mut self, index: usize, value: CaseValue) {
+        if index < self.width * self.height {
+            self.set_mask(self.bit(index), value);
+        }
+    }
+
+    unsafe fn get_unchecked(&self, index: usize) -> CaseValue {
+        let bit = self.bit(index);
+        if self.red & bit != 0 {
+            CaseValue::Red
+        } else if self.blue & bit != 0 {
+            CaseValue::Blue
+        } else if self.wall & bit != 0 {
+            CaseValue::Black
+        } else if self.unlocked & bit != 0 {
+            CaseValue::Yellow
+        } else {
+            CaseValue::White
+        }
+    }
+
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn with_size(width: usize, height: usize) -> Option<Self> {
+        if Self::fits(width, height) {
+            Some(Self::new(width, height))
+        } else {
+            None
+        }
+    }
+
+    fn replace_all(&mut self, value: CaseValue, condition: CaseValue) -> bool {
+        let cases = self.mask(condition);
+        self.set_mask(cases, value);
+        cases != 0
+    }
+
+    fn replace_around_if(
+        &mut self,
+        index: usize,
+        directions: &[Direction],
+        value: CaseValue,
+        condition: CaseValue,
+    ) -> bool {
+        let bit = self.bit(index);
+        let around = directions
+            .iter()
+            .fold(0, |around, dir| around | self.shift(bit, *dir));
+        let cases = around & self.mask(condition);
+        self.set_mask(cases, value);
+        cases != 0
+    }
+
+    fn next_yellow(&self, from: usize) -> Option<usize> {
+        if from >= self.width * self.height {
+            return None;
+        }
+        let cases = self.unlocked & shl(u128::MAX, from + from / self.width);
+        if cases == 0 {
+            return None;
+        }
+        Some(self.index(cases.trailing_zeros() as usize))
+    }
+
+    fn count_yellow(&self) -> usize {
+        self.unlocked.count_ones() as usize
+    }
+
+    fn nth_yellow(&self, n: usize) -> Option<usize> {
+        let mut cases = self.unlocked;
+        for _ in 0..n {
+            cases &= cases.wrapping_sub(1);
+        }
+        if cases == 0 {
+            return None;
+        }
+        Some(self.index(cases.trailing_zeros() as usize))
+    }
+
+    fn find_line(&self, index: usize, color: CaseValue, length: usize) -> Option<Direction> {
+        let bit = self.bit(index);
+        let stones = self.mask(color);
+        [
+            (1, Direction::East),
+            (self.stride(), Direction::South),
+            (self.stride() + 1, Direction::SouthEast),
+            (self.stride() - 1, Direction::SouthWest),
+        ]
+        .iter()
+        .find(|(step, _)| {
+            // Keeps the first case of every `length` aligned stones
+            let starts = (1..length).fold(stones, |starts, i| starts & shr(stones, i * step));
+            (0..length).any(|i| shl(starts, i * step) & bit != 0)
+        })
+        .map(|(_, dir)| *dir)
+    }
+}
+
+// Shifts that drop every bit instead of overflowing when going past the mask
+fn shl(mask: u128, n: usize) -> u128 {
+    mask.checked_shl(n as u32).unwrap_or(0)
+}
+
+fn shr(mask: u128, n: usize) -> u128 {
+    mask.checked_shr(n as u32).unwrap_or(0)
+}
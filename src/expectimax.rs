@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use crate::{
+    grid::{Grid, GridProvider},
+    search::Evaluator,
+    solver::{outcome_value, GameValue},
+};
+
+// Plays against an opponent choosing uniformly among its legal plays, like `random_play`, and
+// maximises the expected score (1 for a win, 0 for a draw and -1 for a loss), which is what
+// `compare_random` measures. Positions more than `depth` plays away are scored by the evaluator, so
+// with a depth reaching the end of the game the score is the best any player can expect against
+// random plays.
+pub struct Expectimax<E: Evaluator> {
+    evaluator: E,
+    pub depth: u8,
+    // Expected score, the depth it was searched at and whether every line reached the end of the
+    // game, by position and whether we are to move
+    memo: HashMap<(u64, bool), (u8, f32, bool)>,
+    pub nodes: u64,
+    // The last `solve` reached the end of the game on every line, so its score is the expected
+    // score and not an estimate of the evaluator
+    pub exact: bool,
+}
+
+impl<E: Evaluator> Expectimax<E> {
+    pub fn new(evaluator: E, depth: u8) -> Self {
+        Self {
+            evaluator,
+            depth,
+            memo: HashMap::new(),
+            nodes: 0,
+            exact: true,
+        }
+    }
+
+    pub fn best_play<T: GridProvider + Clone>(&mut self, grid: &Grid<T>) -> usize {
+        self.solve(grid).1.unwrap()
+    }
+
+    // Expected score of the side to move and the play that gets it
+    pub fn solve<T: GridProvider + Clone>(&mut self, grid: &Grid<T>) -> (f32, Option<usize>) {
+        let mut grid = grid.clone();
+        if grid.is_over() {
+            let (score, exact) = self.value(&mut grid, 0, true);
+            self.exact = exact;
+            return (score, None);
+        }
+        // The plays are always looked at
+        let depth = self.depth.max(1);
+        let mut best = (-f32::INFINITY, None);
+        self.exact = true;
+        let mut next = grid.next_legal_move(0);
+        while let Some(index) = next {
+            grid.play(index).unwrap();
+            let (score, exact) = self.value(&mut grid, depth - 1, false);
+            grid.undo();
+            self.exact &= exact;
+            if score > best.0 {
+                best = (score, Some(index));
+            }
+            next = grid.next_legal_move(index + 1);
+        }
+        best
+    }
+
+    // Score for us and whether it is exact, `is_ours` tells whether we are the side to move
+    fn value<T: GridProvider + Clone>(
+        &mut self,
+        grid: &mut Grid<T>,
+        depth: u8,
+        is_ours: bool,
+    ) -> (f32, bool) {
+        let sign = if is_ours { 1. } else { -1. };
+        if let Some(value) = outcome_value(grid.status(), grid.is_red_turn()) {
+            let score = match value {
+                GameValue::Win => 1.,
+                GameValue::Draw => 0.,
+                GameValue::Loss => -1.,
+            };
+            return (sign * score, true);
+        }
+        if depth == 0 {
+            return (sign * self.evaluator.evaluate(grid), false);
+        }
+        if let Some((searched, score, exact)) = self.memo.get(&(grid.hash(), is_ours)) {
+            if *searched >= depth || *exact {
+                return (*score, *exact);
+            }
+        }
+        self.nodes += 1;
+        let mut best = -f32::INFINITY;
+        let mut sum = 0.;
+        let mut count = 0;
+        let mut exact = true;
+        let mut next = grid.next_legal_move(0);
+        while let Some(index) = next {
+            grid.play(index).unwrap();
+            let (score, is_exact) = self.value(grid, depth - 1, !is_ours);
+            grid.undo();
+            exact &= is_exact;
+            best = best.max(score);
+            sum += score;
+            count += 1;
+            next = grid.next_legal_move(index + 1);
+        }
+        let score = if is_ours { best } else { sum / count as f32 };
+        self.memo
+            .insert((grid.hash(), is_ours), (depth, score, exact));
+        (score, exact)
+    }
+}
@@ -11,25 +11,53 @@ use std::{
 };
 
 use lib_neural_network::{nlib::Layer, LayerTopology, Network};
+use rand::{prelude::StdRng, Rng, SeedableRng};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
-use crate::grid::{CaseValue, Grid, GridProvider, VecProvider};
+use crate::{
+    bitboard::BitboardProvider,
+    grid::{CaseValue, Grid, GridProvider, MoveOutcome},
+    player::play_game,
+    pns::ProofSearch,
+    rules::Rules,
+    tablebase::Tablebase,
+};
 
 #[derive(Clone)]
 pub struct Bot {
     /* layer_in: Layer<{ 5 * 6 }, { 5 * 6 }>,
     layer_hidden: Layer<{ 5 * 6 }, 1>, */
     net: Network,
+    // Endgames found in the tablebase are played perfectly instead of asking the network
+    tablebase: Option<Arc<Tablebase>>,
+    // Plays proven to lose are skipped unless they all are
+    proof_search: Option<ProofSearch>,
 }
 
 impl Bot {
-    pub fn new() -> Self {
+    pub fn new(rng: &mut impl Rng) -> Self {
         Self {
             net: Network::random(
-                &mut rand::thread_rng(),
+                rng,
                 &[LayerTopology(6 * 5), LayerTopology(6 * 5), LayerTopology(1)],
             ), /* layer_in: Layer::random(),
                layer_hidden: Layer::random(), */
+            tablebase: None,
+            proof_search: None,
+        }
+    }
+
+    pub fn with_tablebase(self, tablebase: Arc<Tablebase>) -> Self {
+        Self {
+            tablebase: Some(tablebase),
+            ..self
+        }
+    }
+
+    pub fn with_proof_search(self, proof_search: ProofSearch) -> Self {
+        Self {
+            proof_search: Some(proof_search),
+            ..self
         }
     }
 
@@ -42,6 +70,8 @@ impl Bot {
     pub fn load_save(n: usize) -> Self {
         Bot {
             net: Network::load(&std::fs::read_to_string(&format!("saves/{}.json", n)).unwrap()),
+            tablebase: None,
+            proof_search: None,
         }
     }
 
@@ -50,29 +80,31 @@ impl Bot {
         var: Arc<Mutex<Option<Bot>>>,
         this: Arc<Bot>,
         mutation_ratio: f64,
+        seed: u64,
     ) -> JoinHandle<()> {
         std::thread::spawn(move || {
+            let mut rng = StdRng::seed_from_u64(seed);
             let i = Instant::now();
             let cscore = (0..100)
-                .map(|_| crate::compare_random(&this).score())
+                .map(|_| crate::compare_random(&this, &mut rng).score())
                 .sum::<isize>()
                 / 100;
             loop {
                 if ol.load(std::sync::atomic::Ordering::SeqCst) {
                     break;
                 }
-                let i1 = this.mutate(mutation_ratio);
+                let i1 = this.mutate(mutation_ratio, &mut rng);
                 let p = this.other_win(&i1);
                 if p == -1 {
                     continue;
                 }
-                let s = crate::compare_random(&i1).score();
+                let s = crate::compare_random(&i1, &mut rng).score();
                 if s > cscore - 40 {
                     if ol.load(std::sync::atomic::Ordering::SeqCst) {
                         break;
                     }
                     let bs = (0..100)
-                        .map(|_| crate::compare_random(&i1).score())
+                        .map(|_| crate::compare_random(&i1, &mut rng).score())
                         .sum::<isize>()
                         / 100;
                     if ol.load(std::sync::atomic::Ordering::SeqCst) {
@@ -95,34 +127,42 @@ impl Bot {
         })
     }
 
-    pub fn evolve_mt(self, mutation_ratio: f64) -> Self {
+    pub fn evolve_mt(self, mutation_ratio: f64, rng: &mut impl Rng) -> Self {
         let var: Arc<Mutex<Option<Bot>>> = Arc::new(Mutex::new(None));
         let this = Arc::new(self);
         let bo = Arc::new(AtomicBool::new(false));
         let k: Vec<JoinHandle<()>> = (0..8)
-            .map(|_| Self::build_handle(bo.clone(), var.clone(), this.clone(), mutation_ratio))
+            .map(|_| {
+                Self::build_handle(
+                    bo.clone(),
+                    var.clone(),
+                    this.clone(),
+                    mutation_ratio,
+                    rng.gen(),
+                )
+            })
             .collect();
         k.into_iter().for_each(|x| x.join().unwrap());
         let k = var.lock().unwrap();
         k.as_ref().unwrap().clone()
     }
 
-    pub fn evolve(self, mutation_ratio: f64) -> Self {
+    pub fn evolve(self, mutation_ratio: f64, rng: &mut impl Rng) -> Self {
         let i = Instant::now();
         let cscore = (0..100)
-            .map(|_| crate::compare_random(&self).score())
+            .map(|_| crate::compare_random(&self, rng).score())
             .sum::<isize>()
             / 100;
         loop {
-            let i1 = self.mutate(mutation_ratio);
+            let i1 = self.mutate(mutation_ratio, rng);
             let p = self.other_win(&i1);
             if p == -1 {
                 continue;
             }
-            let s = crate::compare_random(&i1).score();
+            let s = crate::compare_random(&i1, rng).score();
             if s > cscore - 40 {
                 let bs = (0..100)
-                    .map(|_| crate::compare_random(&i1).score())
+                    .map(|_| crate::compare_random(&i1, rng).score())
                     .sum::<isize>()
                     / 100;
                 if bs > cscore {
@@ -139,52 +179,39 @@ impl Bot {
         }
     }
 
+    // Game between this bot as red and `s` as blue: -1 when the player who completed a line wins,
+    // 1 when it loses and 0 for a draw. With the default misère rules every finished game gives 1,
+    // which is what the training has always relied on.
     pub fn other_win(&self, s: &Self) -> isize {
-        let mut grid = Grid::new(VecProvider::new(5, 6));
-        grid.is_red_turn = true;
-        loop {
-            //println!("{}", grid);
-            match grid.play(self.best_play(&grid, CaseValue::Red)) {
-                crate::grid::PlayResult::InvalidPosition => unreachable!(),
-                crate::grid::PlayResult::Played => (),
-                crate::grid::PlayResult::RedWin => {
-                    //println!("{}", grid);
-                    //println!("Red wins 2");
-                    return -1;
-                }
-                crate::grid::PlayResult::BlueWin => return 1,
-                crate::grid::PlayResult::NobodyWin => {
-                    println!("Nobody wins");
-                    return 0;
-                }
-            }
-            match grid.play(s.best_play(&grid, CaseValue::Blue)) {
-                crate::grid::PlayResult::InvalidPosition => unreachable!(),
-                crate::grid::PlayResult::Played => (),
-                crate::grid::PlayResult::BlueWin => return -1,
-                crate::grid::PlayResult::RedWin => {
-                    //println!("{}", grid);
-                    //println!("Red wins 1");
-                    return 1;
-                }
-                crate::grid::PlayResult::NobodyWin => {
-                    //println!("Nobody wins");
-                    return 0;
-                }
-            }
+        let grid = play_game(
+            Grid::new(BitboardProvider::new(5, 6), Rules::default()),
+            &mut self.clone(),
+            &mut s.clone(),
+        );
+        let last_is_red = !grid.is_red_turn();
+        match grid.status() {
+            MoveOutcome::RedWin(_) if last_is_red => -1,
+            MoveOutcome::BlueWin(_) if !last_is_red => -1,
+            MoveOutcome::RedWin(_) | MoveOutcome::BlueWin(_) => 1,
+            _ => 0,
         }
     }
 
-    pub fn mutate(&self, mutation_ratio: f64) -> Self {
+    pub fn mutate(&self, mutation_ratio: f64, rng: &mut impl Rng) -> Self {
         Self {
-            net: self.net.mutate(mutation_ratio), /* layer_in: self.layer_in.mutate(),
+            net: self.net.mutate(mutation_ratio, rng), /* layer_in: self.layer_in.mutate(),
                                                   layer_hidden: self.layer_hidden.mutate(), */
+            tablebase: self.tablebase.clone(),
+            proof_search: self.proof_search,
         }
     }
 
-    pub fn execute(&self, grid: &Grid<VecProvider>, color: CaseValue) -> f32 {
-        let cases/*: [f32; { 6 * 5 }] */ = grid
-            .cases
+    pub fn execute<T: GridProvider + Clone>(&self, grid: &Grid<T>, color: CaseValue) -> f32 {
+        self.execute_cases(&grid.cases, color)
+    }
+
+    fn execute_cases<T: GridProvider>(&self, cases: &T, color: CaseValue) -> f32 {
+        let cases/*: [f32; { 6 * 5 }] */ = cases
             .iter()
             .map(|x| match x {
                 crate::grid::CaseValue::Red => {
@@ -214,17 +241,35 @@ impl Bot {
         *cases.get(0).unwrap() */
     }
 
-    pub fn best_play(&self, grid: &Grid<VecProvider>, color: CaseValue) -> usize {
-        let mut p = grid.clone();
-        let o = grid.get_yellows();
-        let mut o = o.into_iter();
+    pub fn best_play<T: GridProvider + Clone>(&self, grid: &Grid<T>, color: CaseValue) -> usize {
+        if let Some(index) = self
+            .tablebase
+            .as_ref()
+            .and_then(|x| grid.probe(x))
+            .and_then(|x| x.best_move)
+        {
+            return index;
+        }
+        // Plays proven to lose are left out as long as another play remains
+        let is_losing = |losing: &[(usize, u32)], index: usize| losing.iter().any(|x| x.0 == index);
+        let losing = self
+            .proof_search
+            .map(|x| x.losing_moves(grid))
+            .filter(|losing| grid.legal_moves().any(|x| !is_losing(losing, x)));
+        // Only the cases are copied, the network doesn't look at the history
+        let mut p = grid.cases.clone();
+        let mut o = grid.legal_moves().filter(|x| {
+            losing
+                .as_ref()
+                .map_or(true, |losing| !is_losing(losing, *x))
+        });
         let t = o.next().unwrap();
         p.set(t, color);
-        let mut k = (self.execute(&p, color), t);
+        let mut k = (self.execute_cases(&p, color), t);
         p.set(t, CaseValue::Yellow);
         while let Some(t) = o.next() {
             p.set(t, color);
-            let n = (self.execute(&p, color), t);
+            let n = (self.execute_cases(&p, color), t);
             p.set(t, CaseValue::Yellow);
             if n.0 < k.0 {
                 k = n;
@@ -5,8 +5,23 @@ where
 pub struct Grid<T: GridProvider + Clone> {
     pub cases: T, /* [CaseValue; WIDTH * HEIGHT] */
     last_play: Option<usize>,
-    pub is_red_turn: bool,
+    is_red_turn: bool,
     was_everything_yellow: bool,
+    rules: Rules,
+    history: Vec<Move>,
+    redo_stack: Vec<usize>,
+    hash: u64,
+    status: MoveOutcome,
+}
+
+// Everything needed to take back a play, the yellow and white cases are rebuilt from
+// `last_play` and `was_everything_yellow`
+#[derive(Clone, Copy, Debug)]
+struct Move {
+    index: usize,
+    last_play: Option<usize>,
+    was_everything_yellow: bool,
+    hash: u64,
 }
 
 impl<T: GridProvider + Clone> Display for Grid<T> {
@@ -44,36 +59,35 @@ pub struct ArrayProvider<const WIDTH: usize, const HEIGHT: usize> {
 }
 
 impl<const WIDTH: usize, const HEIGHT: usize> GridProvider for ArrayProvider<WIDTH, HEIGHT> {
-    fn get(&self, index: usize) -> Option<&CaseValue> {
-        self.cases.get(index)
-    }
-
-    fn get_mut(&mut self, index: usize) -> Option<&mut CaseValue> {
-        self.cases.get_mut(index)
-    }
-
-    unsafe fn get_unchecked(&self, index: usize) -> &CaseValue {
-        self.cases.get_unchecked(index)
+    fn get(&self, index: usize) -> Option<CaseValue> {
+        self.cases.get(index).copied()
     }
 
-    fn iter_mut(&mut self) -> IterMut<CaseValue> {
-        self.cases.iter_mut()
+    fn set(&mut self, index: usize, value: CaseValue) {
+        if let Some(e) = self.cases.get_mut(index) {
+            *e = value;
+        }
     }
 
-    unsafe fn get_unchecked_mut(&mut self, index: usize) -> &mut CaseValue {
-        self.cases.get_unchecked_mut(index)
+    unsafe fn get_unchecked(&self, index: usize) -> CaseValue {
+        *self.cases.get_unchecked(index)
     }
 
     fn width(&self) -> usize {
         WIDTH
     }
 
-    fn height(&self) -> usize {
-        HEIGHT
+    fn with_size(width: usize, height: usize) -> Option<Self> {
+        if width != WIDTH || height != HEIGHT {
+            return None;
+        }
+        Some(Self {
+            cases: (0..(width * height)).map(|_| CaseValue::Yellow).collect(),
+        })
     }
 
-    fn iter(&self) -> Iter<CaseValue> {
-        self.cases.iter()
+    fn height(&self) -> usize {
+        HEIGHT
     }
 }
 
@@ -88,64 +102,366 @@ impl VecProvider {
 }
 
 impl GridProvider for VecProvider {
-    fn get(&self, index: usize) -> Option<&CaseValue> {
-        self.cases.get(index)
+    fn get(&self, index: usize) -> Option<CaseValue> {
+        self.cases.get(index).copied()
     }
 
-    fn get_mut(&mut self, index: usize) -> Option<&mut CaseValue> {
-        self.cases.get_mut(index)
+    fn set(&mut self, index: usize, value: CaseValue) {
+        if let Some(e) = self.cases.get_mut(index) {
+            *e = value;
+        }
     }
 
-    unsafe fn get_unchecked(&self, index: usize) -> &CaseValue {
-        self.cases.get_unchecked(index)
+    unsafe fn get_unchecked(&self, index: usize) -> CaseValue {
+        *self.cases.get_unchecked(index)
     }
 
-    fn iter_mut(&mut self) -> IterMut<CaseValue> {
-        self.cases.iter_mut()
+    fn width(&self) -> usize {
+        self.width
     }
 
-    fn iter(&self) -> Iter<CaseValue> {
-        self.cases.iter()
+    fn height(&self) -> usize {
+        self.height
     }
 
-    unsafe fn get_unchecked_mut(&mut self, index: usize) -> &mut CaseValue {
-        self.cases.get_unchecked_mut(index)
+    fn with_size(width: usize, height: usize) -> Option<Self> {
+        Some(Self::new(width, height))
     }
 
-    fn width(&self) -> usize {
-        self.width
+    fn replace_all(&mut self, value: CaseValue, condition: CaseValue) -> bool {
+        let mut p = false;
+        self.cases.iter_mut().for_each(|x| {
+            if *x == condition {
+                p = true;
+                *x = value
+            }
+        });
+        p
     }
 
-    fn height(&self) -> usize {
-        self.height
+    fn replace_around_if(
+        &mut self,
+        index: usize,
+        directions: &[Direction],
+        value: CaseValue,
+        condition: CaseValue,
+    ) -> bool {
+        let mut p = false;
+        for dir in directions {
+            if let Some(u) = self.follow(index, *dir) {
+                // Perfectly safe since `follow` check bounds.
+                let o = unsafe { self.cases.get_unchecked_mut(u) };
+                if *o == condition {
+                    p = true;
+                    *o = value;
+                }
+            }
+        }
+        p
     }
 }
 
 pub trait GridProvider {
-    fn get(&self, index: usize) -> Option<&CaseValue>;
-    fn get_mut(&mut self, index: usize) -> Option<&mut CaseValue>;
-    unsafe fn get_unchecked(&self, index: usize) -> &CaseValue;
-    fn iter_mut(&mut self) -> IterMut<CaseValue>;
-    unsafe fn get_unchecked_mut(&mut self, index: usize) -> &mut CaseValue;
+    fn get(&self, index: usize) -> Option<CaseValue>;
+    fn set(&mut self, index: usize, value: CaseValue);
+    unsafe fn get_unchecked(&self, index: usize) -> CaseValue;
     fn width(&self) -> usize;
     fn height(&self) -> usize;
-    fn iter(&self) -> Iter<CaseValue>;
+    // None when the provider can't hold a grid of this size
+    fn with_size(width: usize, height: usize) -> Option<Self>
+    where
+        Self: Sized;
+
+    fn iter(&self) -> Cases<'_, Self>
+    where
+        Self: Sized,
+    {
+        Cases {
+            provider: self,
+            index: 0,
+        }
+    }
+
+    fn follow(&self, index: usize, dir: Direction) -> Option<usize> {
+        let u = (index as isize) + dir.offset(self.width());
+        if u < 0 {
+            None
+        } else if u >= ((self.width() * self.height()) as isize) {
+            None
+        } else {
+            let u = u as usize;
+            match dir {
+                Direction::North => Some(u),
+                Direction::East | Direction::SouthEast | Direction::NorthEast => {
+                    if u % self.width() == 0 {
+                        None
+                    } else {
+                        Some(u)
+                    }
+                }
+                Direction::West | Direction::NorthWest | Direction::SouthWest => {
+                    if (u + 1) % self.width() == 0 {
+                        None
+                    } else {
+                        Some(u)
+                    }
+                }
+                Direction::South => Some(u),
+            }
+        }
+    }
+
+    // Replaces every `condition` case by `value`, returns whether something was replaced
+    fn replace_all(&mut self, value: CaseValue, condition: CaseValue) -> bool {
+        let mut p = false;
+        for index in 0..(self.width() * self.height()) {
+            // Perfectly safe since the index is in the grid.
+            if unsafe { self.get_unchecked(index) } == condition {
+                p = true;
+                self.set(index, value);
+            }
+        }
+        p
+    }
+
+    // Replaces the `condition` cases next to `index` by `value`, returns whether something was
+    // replaced
+    fn replace_around_if(
+        &mut self,
+        index: usize,
+        directions: &[Direction],
+        value: CaseValue,
+        condition: CaseValue,
+    ) -> bool {
+        let mut p = false;
+        for dir in directions {
+            if let Some(u) = self.follow(index, *dir) {
+                // Perfectly safe since `follow` check bounds.
+                if unsafe { self.get_unchecked(u) } == condition {
+                    p = true;
+                    self.set(u, value);
+                }
+            }
+        }
+        p
+    }
+
+    // Number of consecutive `color` cases starting next to `index` and going toward `dir`
+    fn run_length(&self, index: usize, dir: Direction, color: CaseValue) -> usize {
+        let mut count = 0;
+        let mut current = index;
+        while let Some(u) = self.follow(current, dir) {
+            // Perfectly safe since `follow` check bounds.
+            if unsafe { self.get_unchecked(u) } != color {
+                break;
+            }
+            count += 1;
+            current = u;
+        }
+        count
+    }
+
+    fn next_yellow(&self, from: usize) -> Option<usize> {
+        // Perfectly safe since the index is in the grid.
+        (from..(self.width() * self.height()))
+            .find(|index| unsafe { self.get_unchecked(*index) } == CaseValue::Yellow)
+    }
+
+    fn count_yellow(&self) -> usize {
+        let mut count = 0;
+        let mut next = self.next_yellow(0);
+        while let Some(index) = next {
+            count += 1;
+            next = self.next_yellow(index + 1);
+        }
+        count
+    }
+
+    // The `n`th playable case, counting from 0
+    fn nth_yellow(&self, n: usize) -> Option<usize> {
+        let mut next = self.next_yellow(0);
+        for _ in 0..n {
+            next = self.next_yellow(next? + 1);
+        }
+        next
+    }
+
+    // Axis, taken from `LINE_AXES`, on which `index` is part of at least `length` aligned `color`
+    // cases
+    fn find_line(&self, index: usize, color: CaseValue, length: usize) -> Option<Direction> {
+        LINE_AXES.iter().copied().find(|dir| {
+            self.run_length(index, *dir, color) + self.run_length(index, dir.mirror(), color) + 1
+                >= length
+        })
+    }
+}
+
+pub struct LegalMoves<'a, T> {
+    cases: &'a T,
+    next: usize,
+}
+
+impl<'a, T: GridProvider> Iterator for LegalMoves<'a, T> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let index = self.cases.next_yellow(self.next)?;
+        self.next = index + 1;
+        Some(index)
+    }
+}
+
+pub struct Cases<'a, T> {
+    provider: &'a T,
+    index: usize,
+}
+
+impl<'a, T: GridProvider> Iterator for Cases<'a, T> {
+    type Item = CaseValue;
+
+    fn next(&mut self) -> Option<CaseValue> {
+        let case = self.provider.get(self.index)?;
+        self.index += 1;
+        Some(case)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.provider.width() * self.provider.height() - self.index;
+        (remaining, Some(remaining))
+    }
 }
 
 impl<T: GridProvider + Clone> Grid<T> {
-    pub fn new(grid_provider: T) -> Self {
-        Self {
+    pub fn new(grid_provider: T, rules: Rules) -> Self {
+        let mut grid = Self {
             cases: grid_provider,
             last_play: None,
             is_red_turn: true,
             was_everything_yellow: true,
+            rules,
+            history: Vec::new(),
+            redo_stack: Vec::new(),
+            hash: 0,
+            status: MoveOutcome::Played,
+        };
+        grid.hash = grid.compute_hash();
+        grid.update_full_status();
+        grid
+    }
+
+    // Builds a grid from cases that already hold stones, the game history is lost. The playable
+    // cases are rebuilt from the last play the way `apply` unlocks them.
+    pub fn from_position(
+        grid_provider: T,
+        rules: Rules,
+        is_red_turn: bool,
+        last_play: Option<usize>,
+    ) -> Self {
+        let mut grid = Self::new(grid_provider, rules);
+        grid.is_red_turn = is_red_turn;
+        grid.last_play = last_play;
+        // Everything is unlocked when the last play has no empty case around it
+        grid.was_everything_yellow = last_play.map_or(true, |index| {
+            !grid.rules.neighbourhood.directions().iter().any(|dir| {
+                matches!(grid.follow_and_get(index, *dir), Some((_, x)) if x.empty())
+            })
+        });
+        if let Some(index) = last_play {
+            let color = grid.cases.get(index).unwrap();
+            if let Some(dir) = grid.cases.find_line(index, color, grid.rules.line_length) {
+                grid.status = grid.line_result(index, dir, color);
+            }
         }
+        // A play that ends the game only unlocks the cases around it
+        if grid.was_everything_yellow && !grid.is_over() {
+            grid.cases.replace_all(CaseValue::Yellow, CaseValue::White);
+        } else {
+            grid.cases.replace_all(CaseValue::White, CaseValue::Yellow);
+            if let Some(index) = last_play {
+                grid.replace_around_if(index, CaseValue::Yellow, CaseValue::White);
+            }
+        }
+        grid.hash = grid.compute_hash();
+        grid
+    }
+
+    // A game without any empty case is a draw, even before the first play. Only a full grid gives
+    // `NobodyWin`, so the status is recomputed as long as no line was completed.
+    fn update_full_status(&mut self) {
+        if matches!(self.status, MoveOutcome::Played | MoveOutcome::NobodyWin) {
+            self.status = if self.cases.iter().any(|x| x.empty()) {
+                MoveOutcome::Played
+            } else {
+                MoveOutcome::NobodyWin
+            };
+        }
+    }
+
+    pub fn last_play(&self) -> Option<usize> {
+        self.last_play
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    pub fn is_red_turn(&self) -> bool {
+        self.is_red_turn
+    }
+
+    // Result of the last play, `Played` while the game goes on
+    pub fn status(&self) -> &MoveOutcome {
+        &self.status
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.status.is_over()
     }
 
-    pub fn place_blocks(&mut self, i: usize, rand_func: impl Fn() -> usize) {
+    // Zobrist hash of the position, including the side to move and the playable cases
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    // Smallest hash of the symmetric positions, so all of them share it. The rules and which cases
+    // are unlocked don't change with a symmetry, only the indexes move.
+    pub fn canonical_hash(&self) -> u64 {
+        Symmetry::all(self.width(), self.height())
+            .iter()
+            .map(|symmetry| self.symmetric_hash(*symmetry))
+            .min()
+            .unwrap()
+    }
+
+    // Hash of the position seen through `symmetry`, without building it
+    pub fn symmetric_hash(&self, symmetry: Symmetry) -> u64 {
+        let (width, height) = (self.width(), self.height());
+        let map = |index| symmetry.map(index, width, height);
+        let keys = unlock_key(self.last_play.map(map), self.was_everything_yellow)
+            ^ turn_key(self.is_red_turn);
+        self.cases
+            .iter()
+            .enumerate()
+            .fold(keys, |hash, (index, value)| hash ^ case_key(map(index), value))
+    }
+
+    fn compute_hash(&self) -> u64 {
+        self.cases
+            .iter()
+            .enumerate()
+            .fold(self.unlock_key() ^ turn_key(self.is_red_turn), |hash, (index, value)| {
+                hash ^ case_key(index, value)
+            })
+    }
+
+    fn unlock_key(&self) -> u64 {
+        unlock_key(self.last_play, self.was_everything_yellow)
+    }
+
+    pub fn place_blocks(&mut self, i: usize, rng: &mut impl Rng) {
         for _ in 0..i {
             self.set(
-                rand_func() % (self.height() * self.width()),
+                rng.gen_range(0..(self.height() * self.width())),
                 CaseValue::Black,
             )
         }
@@ -163,122 +479,141 @@ impl<T: GridProvider + Clone> Grid<T> {
         x + y * self.width()
     }
 
-    pub fn get(&self, index: usize) -> Option<&CaseValue> {
+    pub fn index_to_x_y(&self, index: usize) -> (usize, usize) {
+        (index % self.width(), index / self.width())
+    }
+
+    pub fn get(&self, index: usize) -> Option<CaseValue> {
         self.cases.get(index)
     }
 
     pub fn set(&mut self, index: usize, value: CaseValue) {
-        if let Some(e) = self.cases.get_mut(index) {
-            *e = value;
+        if let Some(previous) = self.cases.get(index) {
+            self.hash ^= case_key(index, previous) ^ case_key(index, value);
+            self.cases.set(index, value);
+            // Walls can fill the grid or free a case of it
+            if value == CaseValue::Black || previous == CaseValue::Black {
+                self.update_full_status();
+            }
         }
     }
 
     fn follow(&self, index: usize, dir: Direction) -> Option<usize> {
-        let u = (index as isize) + dir.offset(self.width());
-        if u < 0 {
-            None
-        } else if u >= ((self.width() * self.height()) as isize) {
-            None
-        } else {
-            let u = u as usize;
-            match dir {
-                Direction::North => Some(u),
-                Direction::East | Direction::SouthEast | Direction::NorthEast => {
-                    if u % self.width() == 0 {
-                        None
-                    } else {
-                        Some(u)
-                    }
-                }
-                Direction::West | Direction::NorthWest | Direction::SouthWest => {
-                    if (u + 1) % self.width() == 0 {
-                        None
-                    } else {
-                        Some(u)
-                    }
-                }
-                Direction::South => Some(u),
-            }
-        }
+        self.cases.follow(index, dir)
     }
 
-    // TODO: Optimize this function
-    fn replace_around_if(&mut self, index: usize, value: CaseValue, condition: CaseValue) {
-        for dir in Direction::iter() {
-            if let Some(u) = self.follow(index, dir) {
-                // Perfectly safe since `follow` check bounds.
-                let o = unsafe { self.cases.get_unchecked_mut(u) };
-                if *o == condition {
-                    *o = value;
-                }
-            }
-        }
+    fn replace_around_if(&mut self, index: usize, value: CaseValue, condition: CaseValue) -> bool {
+        self.cases.replace_around_if(
+            index,
+            self.rules.neighbourhood.directions(),
+            value,
+            condition,
+        )
     }
 
-    fn check_direction_and_yellow_first(&mut self, index: usize, compare: CaseValue) -> PlayResult {
-        let mut p = false;
-        let mut dirs: HashSet<Direction> = HashSet::new();
-        for dir in Direction::iter() {
-            if let Some(u) = self.follow(index, dir) {
-                // Perfectly safe since `follow` check bounds.
-                let o = unsafe { self.cases.get_unchecked_mut(u) };
-                if *o == CaseValue::White {
-                    p = true;
-                    *o = CaseValue::Yellow;
-                } else if *o == compare {
-                    dirs.insert(dir);
-                    if dirs.contains(&dir.mirror()) {
-                        if compare == CaseValue::Blue {
-                            return PlayResult::RedWin;
-                        } else {
-                            return PlayResult::BlueWin;
-                        }
-                    }
-                    if let Some(u) = self.follow(u, dir) {
-                        let o = unsafe { self.cases.get_unchecked_mut(u) };
-                        if *o == compare {
-                            if compare == CaseValue::Blue {
-                                return PlayResult::RedWin;
-                            } else {
-                                return PlayResult::BlueWin;
-                            }
-                        }
-                    }
-                }
-            }
+    fn line_result(&self, index: usize, dir: Direction, compare: CaseValue) -> MoveOutcome {
+        let mut start = index;
+        for _ in 0..self.cases.run_length(index, dir.mirror(), compare) {
+            start = self.follow(start, dir.mirror()).unwrap();
         }
-        if !p {
-            self.cases.iter_mut().for_each(|x| {
-                if matches!(x, CaseValue::White) {
-                    p = true;
-                    *x = CaseValue::Yellow
-                }
-            });
-            self.was_everything_yellow = true;
-            if !p {
-                return PlayResult::NobodyWin;
+        let mut cells = vec![start];
+        while let Some((u, o)) = self.follow_and_get(*cells.last().unwrap(), dir) {
+            if o != compare {
+                break;
             }
+            cells.push(u);
+        }
+        let line = Line {
+            cells,
+            direction: dir,
+        };
+        // In misère the color that completed the line loses
+        if (compare == CaseValue::Blue) == self.rules.misere {
+            MoveOutcome::RedWin(line)
         } else {
-            self.was_everything_yellow = false;
+            MoveOutcome::BlueWin(line)
         }
-        PlayResult::Played
     }
 
-    pub fn random_play(&mut self) -> PlayResult {
-        self.play(*self.get_yellows().choose(&mut rand::thread_rng()).unwrap())
+    fn check_direction_and_yellow_first(&mut self, index: usize, compare: CaseValue) -> MoveOutcome {
+        let p = self.replace_around_if(index, CaseValue::Yellow, CaseValue::White);
+        // Set even when the play ends the game, the hash of the finished position depends on it
+        self.was_everything_yellow = !p;
+        if let Some(dir) = self
+            .cases
+            .find_line(index, compare, self.rules.line_length)
+        {
+            return self.line_result(index, dir, compare);
+        }
+        if !p && !self.cases.replace_all(CaseValue::Yellow, CaseValue::White) {
+            return MoveOutcome::NobodyWin;
+        }
+        MoveOutcome::Played
     }
 
-    pub fn play(&mut self, index: usize) -> PlayResult {
+    pub fn random_play(&mut self, rng: &mut impl Rng) -> Result<MoveOutcome, PlayError> {
+        let count = self.legal_move_count();
+        if count == 0 {
+            return Err(PlayError::GameOver);
+        }
+        let n = rng.gen_range(0..count);
+        self.play(self.nth_legal_move(n).unwrap())
+    }
+
+    pub fn play(&mut self, index: usize) -> Result<MoveOutcome, PlayError> {
+        let result = self.apply(index)?;
+        self.redo_stack.clear();
+        Ok(result)
+    }
+
+    // Takes back the last play and returns its index
+    pub fn undo(&mut self) -> Option<usize> {
+        let played = self.history.pop()?;
+        self.set(played.index, CaseValue::White);
+        self.is_red_turn = !self.is_red_turn;
+        self.last_play = played.last_play;
+        self.was_everything_yellow = played.was_everything_yellow;
+        self.hash = played.hash;
+        self.status = MoveOutcome::Played;
+        self.restore_yellows();
+        self.redo_stack.push(played.index);
+        Some(played.index)
+    }
+
+    pub fn redo(&mut self) -> Option<Result<MoveOutcome, PlayError>> {
+        let index = self.redo_stack.pop()?;
+        Some(self.apply(index))
+    }
+
+    pub fn moves(&self) -> impl Iterator<Item = usize> + '_ {
+        self.history.iter().map(|x| x.index)
+    }
+
+    fn restore_yellows(&mut self) {
+        if self.was_everything_yellow {
+            self.cases.replace_all(CaseValue::Yellow, CaseValue::White);
+        } else {
+            self.cases.replace_all(CaseValue::White, CaseValue::Yellow);
+            if let Some(e) = self.last_play {
+                self.replace_around_if(e, CaseValue::Yellow, CaseValue::White);
+            }
+        }
+    }
+
+    fn apply(&mut self, index: usize) -> Result<MoveOutcome, PlayError> {
+        if self.is_over() {
+            return Err(PlayError::GameOver);
+        }
         match self.get(index) {
+            None => Err(PlayError::OutOfBounds),
+            Some(CaseValue::Red) | Some(CaseValue::Blue) => Err(PlayError::Occupied),
+            Some(CaseValue::Black) => Err(PlayError::Wall),
+            Some(CaseValue::White) => Err(PlayError::NotAdjacent),
             Some(CaseValue::Yellow) => {
                 if self.was_everything_yellow {
                     // TODO: Optimization tip | Instead of removing everything only remove something that is not at
                     // 1 of distance of the new play position
-                    self.cases.iter_mut().for_each(|x| {
-                        if matches!(x, CaseValue::Yellow) {
-                            *x = CaseValue::White
-                        }
-                    });
+                    self.cases.replace_all(CaseValue::White, CaseValue::Yellow);
                 } else {
                     if let Some(e) = &self.last_play {
                         self.replace_around_if(*e, CaseValue::White, CaseValue::Yellow);
@@ -289,17 +624,26 @@ impl<T: GridProvider + Clone> Grid<T> {
                 } else {
                     CaseValue::Blue
                 };
+                self.history.push(Move {
+                    index,
+                    last_play: self.last_play,
+                    was_everything_yellow: self.was_everything_yellow,
+                    hash: self.hash,
+                });
+                self.hash ^= self.unlock_key() ^ turn_key(self.is_red_turn);
                 self.set(index, turn_case_color);
                 self.is_red_turn = !self.is_red_turn;
 
                 self.last_play = Some(index);
-                self.check_direction_and_yellow_first(index, turn_case_color)
+                let result = self.check_direction_and_yellow_first(index, turn_case_color);
+                self.hash ^= self.unlock_key() ^ turn_key(self.is_red_turn);
+                self.status = result.clone();
+                Ok(result)
             }
-            _ => PlayResult::InvalidPosition,
         }
     }
 
-    pub fn follow_and_get(&self, index: usize, dir: Direction) -> Option<(usize, &CaseValue)> {
+    pub fn follow_and_get(&self, index: usize, dir: Direction) -> Option<(usize, CaseValue)> {
         if let Some(u) = self.follow(index, dir) {
             // Perfectly safe since `follow` check bounds.
             Some((u, unsafe { self.cases.get_unchecked(u) }))
@@ -308,55 +652,88 @@ impl<T: GridProvider + Clone> Grid<T> {
         }
     }
 
-    fn choose_best_indexes(&self, turn_case_color: CaseValue) -> Vec<(usize, f32)> {
-        let mut i = self
-            .cases
-            .iter()
-            .enumerate()
-            .flat_map(|(index, value)| {
-                if *value == CaseValue::Yellow {
-                    Some((index, self.evaluate_play(index, turn_case_color.invert())))
-                } else {
-                    None
+    // The 3 plays with the lowest `evaluate_play`, sorted
+    fn choose_best_indexes(
+        &self,
+        turn_case_color: CaseValue,
+        params: &HeuristicParams,
+    ) -> [Option<(usize, f32)>; 3] {
+        let mut best = [None; 3];
+        for index in self.legal_moves() {
+            let score = self.evaluate_play(index, turn_case_color.invert(), params);
+            let mut candidate = Some((index, score));
+            for slot in best.iter_mut() {
+                match (*slot, candidate) {
+                    (None, _) => {
+                        *slot = candidate;
+                        break;
+                    }
+                    (Some((_, a)), Some((_, b))) if b < a => std::mem::swap(slot, &mut candidate),
+                    _ => (),
                 }
-            })
-            .collect::<Vec<_>>();
-        i.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
-        i.into_iter().take(3).collect()
+            }
+        }
+        best
     }
 
-    pub fn get_yellows(&self) -> Vec<usize> {
-        self.cases
-            .iter()
-            .enumerate()
-            .filter(|(_, value)| matches!(value, CaseValue::Yellow))
-            .map(|(a, _)| a)
-            .collect()
+    pub fn legal_moves(&self) -> LegalMoves<'_, T> {
+        LegalMoves {
+            cases: &self.cases,
+            next: 0,
+        }
     }
 
-    fn begin_simul(&self, turn_case_color: CaseValue, max_depth: u8) -> Option<(usize, PathIssue)> {
-        if max_depth == 0 {
+    pub fn legal_move_count(&self) -> usize {
+        self.cases.count_yellow()
+    }
+
+    // The `n`th play of `legal_moves`
+    pub fn nth_legal_move(&self, n: usize) -> Option<usize> {
+        self.cases.nth_yellow(n)
+    }
+
+    // First playable case from `from`, lets callers walk the plays while playing and undoing
+    pub fn next_legal_move(&self, from: usize) -> Option<usize> {
+        self.cases.next_yellow(from)
+    }
+
+    fn begin_simul(
+        &mut self,
+        turn_case_color: CaseValue,
+        max_depth: u8,
+        table: &mut SimulTable,
+        params: &HeuristicParams,
+    ) -> Option<(usize, PathIssue)> {
+        if max_depth == 0 || self.is_over() {
             return None;
         }
-        let mut max_points = 0;
+        if let Some((depth, result)) = table.get(self.hash) {
+            if *depth == max_depth {
+                return *result;
+            }
+        }
+        let mut max_points = f32::NEG_INFINITY;
         let mut current = None;
-        for (i, s) in self.choose_best_indexes(turn_case_color) {
+        for (i, s) in self
+            .choose_best_indexes(turn_case_color, params)
+            .iter()
+            .flatten()
+            .copied()
+        {
             let mut current_issue = PathIssue {
                 win: 0,
                 none: 0,
                 lose: 0,
                 nowin: 0,
             };
-            if s < OUR_OUR_COLOR {
-                let mut new = self.clone();
-                new.play(i);
-                for p in self.get_yellows() {
-                    let mut new = self.clone();
-                    match new.play(p) {
-                        PlayResult::RedWin | PlayResult::BlueWin => {
+            if s < params.our_our_color {
+                let mut next = self.next_legal_move(0);
+                while let Some(p) = next {
+                    match self.play(p) {
+                        Ok(MoveOutcome::RedWin(_)) | Ok(MoveOutcome::BlueWin(_)) => {
                             current_issue.win += 1;
                         }
-                        PlayResult::NobodyWin => {
+                        Ok(MoveOutcome::NobodyWin) => {
                             current_issue.nowin += 1;
                         }
                         _ => (),
@@ -369,41 +746,53 @@ impl<T: GridProvider + Clone> Grid<T> {
                             lose,
                             nowin,
                         },
-                    )) = new.begin_simul(turn_case_color, max_depth - 1)
+                    )) = self.begin_simul(turn_case_color, max_depth - 1, table, params)
                     {
                         current_issue.win += win;
                         current_issue.none += none;
                         current_issue.lose += lose;
                         current_issue.nowin += nowin;
                     }
+                    self.undo();
+                    next = self.next_legal_move(p + 1);
                 }
             } else {
                 current_issue.lose += (max_depth * max_depth) as usize;
             }
-            let cc = current_issue.count();
+            let cc = current_issue.count(params);
             if max_points < cc {
                 current = Some((i, current_issue));
                 max_points = cc;
             }
         }
 
+        table.insert(self.hash, (max_depth, current));
         current
     }
 
-    pub fn where_to_play(&self) -> usize {
-        let p = self.begin_simul(
+    pub fn where_to_play(&self, params: &HeuristicParams) -> usize {
+        let p = self.clone().begin_simul(
             if self.is_red_turn {
                 CaseValue::Blue
             } else {
                 CaseValue::Red
             },
             5,
+            &mut TranspositionTable::new(16),
+            params,
         );
         //ConsoleService::log(&format!("{:?}", p));
         p.unwrap().0
     }
 
-    pub fn evaluate_play(&self, index: usize, color: CaseValue) -> f32 {
+    // Whether a stone of `color` at `index` would complete a line
+    pub fn completes_line(&self, index: usize, color: CaseValue) -> bool {
+        self.cases
+            .find_line(index, color, self.rules.line_length)
+            .is_some()
+    }
+
+    pub fn evaluate_play(&self, index: usize, color: CaseValue, params: &HeuristicParams) -> f32 {
         let mut score = 0.;
         let other = color.invert();
         let mut play_everywhere = true;
@@ -413,55 +802,57 @@ impl<T: GridProvider + Clone> Grid<T> {
                     play_everywhere = false;
                     if let Some((_, o)) = self.follow_and_get(u, dir) {
                         if o.empty() {
-                            score += EMPTY_EMPTY;
-                        } else if *o == color {
-                            score += EMPTY_OUR_COLOR;
+                            score += params.empty_empty;
+                        } else if o == color {
+                            score += params.empty_our_color;
                         }
                     }
                     if let Some((_, o)) = self.follow_and_get(index, dir.mirror()) {
                         if o.empty() {
-                            score += EMPTY_EMPTY;
-                        } else if *o == color {
-                            score += EMPTY_OUR_COLOR;
+                            score += params.empty_empty;
+                        } else if o == color {
+                            score += params.empty_our_color;
                         }
                     }
-                } else if *o == color {
+                } else if o == color {
                     if let Some((_, o)) = self.follow_and_get(u, dir) {
                         if o.empty() {
-                            score += EMPTY_OUR_COLOR;
-                        } else if *o == color {
-                            score += OUR_OUR_COLOR;
+                            score += params.empty_our_color;
+                        } else if o == color {
+                            score += params.our_our_color;
                         }
                     }
                     if let Some((_, o)) = self.follow_and_get(index, dir.mirror()) {
                         if o.empty() {
-                            score += EMPTY_OUR_COLOR;
-                        } else if *o == color {
-                            score += OUR_OUR_COLOR;
+                            score += params.empty_our_color;
+                        } else if o == color {
+                            score += params.our_our_color;
                         }
                     }
-                } else if *o == other {
+                } else if o == other {
                     if let Some((_, o)) = self.follow_and_get(u, dir) {
-                        if *o == other {
-                            score += THEIR_THEIR_COLOR;
+                        if o == other {
+                            score += params.their_their_color;
                         }
                     }
                     if let Some((_, o)) = self.follow_and_get(index, dir.mirror()) {
-                        if *o == other {
-                            score += THEIR_THEIR_COLOR;
+                        if o == other {
+                            score += params.their_their_color;
                         }
                     }
                 }
             }
         }
         if play_everywhere {
-            score += PLAY_EVERYWHERE;
+            score += params.play_everywhere;
         }
         score
     }
 }
 
-#[derive(Debug)]
+type SimulTable = TranspositionTable<(u8, Option<(usize, PathIssue)>)>;
+
+#[derive(Clone, Copy, Debug)]
 struct PathIssue {
     win: usize,
     none: usize,
@@ -470,16 +861,18 @@ struct PathIssue {
 }
 
 impl PathIssue {
-    fn count(&self) -> usize {
-        usize::MAX / 2 + self.win * 1 - self.lose * 50
+    fn count(&self, params: &HeuristicParams) -> f32 {
+        self.win as f32 * params.win_weight - self.lose as f32 * params.lose_weight
     }
 }
 
-const EMPTY_EMPTY: f32 = 0.05;
-const EMPTY_OUR_COLOR: f32 = 2.;
-const THEIR_THEIR_COLOR: f32 = 0.5;
-const OUR_OUR_COLOR: f32 = 1000.;
-const PLAY_EVERYWHERE: f32 = 4.;
+// One direction per axis, lines are checked on both sides of the played case
+const LINE_AXES: [Direction; 4] = [
+    Direction::East,
+    Direction::South,
+    Direction::SouthEast,
+    Direction::SouthWest,
+];
 
 // X X X X X
 // X X X X X
@@ -487,18 +880,24 @@ const PLAY_EVERYWHERE: f32 = 4.;
 // X X X O X
 // X X X B X
 
-use std::{
-    collections::HashSet,
-    fmt::Display,
-    slice::{Iter, IterMut},
-};
+use std::fmt::Display;
+
+use rand::Rng;
 
-use rand::prelude::SliceRandom;
+use crate::{
+    heuristic::HeuristicParams,
+    rules::Rules,
+    symmetry::Symmetry,
+    transposition::TranspositionTable,
+    zobrist::{case_key, turn_key, unlock_key},
+};
 use strum::IntoEnumIterator; // 0.17.1
 use strum_macros::EnumIter;
 // use yew::services::ConsoleService; // 0.17.1
 
-#[derive(EnumIter, Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[derive(
+    EnumIter, Clone, Copy, Debug, Hash, PartialEq, Eq, serde::Deserialize, serde::Serialize,
+)]
 pub enum Direction {
     North,
     East,
@@ -538,15 +937,48 @@ impl Direction {
     }
 }
 
-#[derive(PartialEq)]
-pub enum PlayResult {
-    InvalidPosition,
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum MoveOutcome {
     Played,
-    RedWin,
-    BlueWin,
+    RedWin(Line),
+    BlueWin(Line),
     NobodyWin,
 }
 
+// Aligned cases that ended the game, in order toward `direction`
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Line {
+    pub cells: Vec<usize>,
+    pub direction: Direction,
+}
+
+impl MoveOutcome {
+    pub fn is_over(&self) -> bool {
+        !matches!(self, Self::Played)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlayError {
+    OutOfBounds,
+    Occupied,
+    Wall,
+    NotAdjacent,
+    GameOver,
+}
+
+impl Display for PlayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OutOfBounds => write!(f, "this case is out of the grid"),
+            Self::Occupied => write!(f, "this case is already taken"),
+            Self::Wall => write!(f, "this case is a wall"),
+            Self::NotAdjacent => write!(f, "this case is not next to the last play"),
+            Self::GameOver => write!(f, "the game is already over"),
+        }
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum CaseValue {
     Red,
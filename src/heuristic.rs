@@ -0,0 +1,147 @@
+use rand::Rng;
+
+use crate::{
+    grid::{Grid, GridProvider},
+    player::{play_match, random_openings, HeuristicPlayer},
+};
+
+const PARAMS_FILE: &str = "heuristic.json";
+
+// Weights of `evaluate_play` and of the simulations of `where_to_play`
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct HeuristicParams {
+    // Scores of a play by the two cases after it on a line, the lowest score is played
+    pub empty_empty: f32,
+    pub empty_our_color: f32,
+    pub their_their_color: f32,
+    // Completes a line, so this one must stay far above the sum of the others
+    pub our_our_color: f32,
+    // No empty case around the play, the opponent can play anywhere
+    pub play_everywhere: f32,
+    // Weights of the won and lost games of a simulation
+    pub win_weight: f32,
+    pub lose_weight: f32,
+    // Weights of the terms of the search heuristic, missing from files saved before them
+    #[serde(default = "default_safe_share")]
+    pub safe_share: f32,
+    #[serde(default = "default_safe_balance")]
+    pub safe_balance: f32,
+    #[serde(default = "default_safe_sums")]
+    pub safe_sums: f32,
+}
+
+fn default_safe_share() -> f32 {
+    HeuristicParams::DEFAULT.safe_share
+}
+
+fn default_safe_balance() -> f32 {
+    HeuristicParams::DEFAULT.safe_balance
+}
+
+fn default_safe_sums() -> f32 {
+    HeuristicParams::DEFAULT.safe_sums
+}
+
+impl HeuristicParams {
+    pub const DEFAULT: Self = Self {
+        empty_empty: 0.05,
+        empty_our_color: 2.,
+        their_their_color: 0.5,
+        our_our_color: 1000.,
+        play_everywhere: 4.,
+        win_weight: 1.,
+        lose_weight: 50.,
+        safe_share: 0.85,
+        safe_balance: 0.1,
+        safe_sums: 0.05,
+    };
+
+    // The tuned parameters if they were saved, the default ones otherwise
+    pub fn load() -> Self {
+        std::fs::read_to_string(PARAMS_FILE)
+            .map(|x| serde_json::from_str(&x).unwrap())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        std::fs::write(PARAMS_FILE, serde_json::to_string_pretty(self).unwrap()).unwrap();
+        println!("Parameters saved in `{}`", PARAMS_FILE);
+    }
+
+    // Only the ratios matter, so `our_our_color` and `win_weight` are left out of the tuning
+    fn tuned(&self) -> [f32; 5] {
+        [
+            self.empty_empty,
+            self.empty_our_color,
+            self.their_their_color,
+            self.play_everywhere,
+            self.lose_weight,
+        ]
+    }
+
+    fn with_tuned(&self, x: [f32; 5]) -> Self {
+        Self {
+            empty_empty: x[0],
+            empty_our_color: x[1],
+            their_their_color: x[2],
+            play_everywhere: x[3],
+            lose_weight: x[4],
+            ..*self
+        }
+    }
+}
+
+impl Default for HeuristicParams {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+// SPSA by self-play: every iteration moves all the parameters at once in a random direction and
+// plays `games` games between both sides of it. The parameters are positive so they are tuned in
+// log space.
+pub fn tune<T: GridProvider + Clone>(
+    start: &Grid<T>,
+    params: HeuristicParams,
+    iterations: usize,
+    games: usize,
+    rng: &mut impl Rng,
+) -> HeuristicParams {
+    // Usual SPSA gain sequences, a 40% score moves the parameters by about 10% early on
+    let (a, c, stability) = (0.5, 0.3, iterations as f32 / 10.);
+    // `evaluate_play` adds up to 16 of the first four weights, together they must stay below a
+    // completed line
+    let max = (params.our_our_color / 17.).ln();
+    let clamp = |x: &mut [f32; 5]| x[..4].iter_mut().for_each(|x| *x = x.min(max));
+    let mut x = params.tuned().map(f32::ln);
+    clamp(&mut x);
+    for k in 0..iterations {
+        let a_k = a / (k as f32 + 1. + stability).powf(0.602);
+        let c_k = c / (k as f32 + 1.).powf(0.101);
+        let delta = [(); 5].map(|_| if rng.gen() { 1. } else { -1. });
+        let shifted = |sign: f32| {
+            let mut y = x;
+            for (y, d) in y.iter_mut().zip(&delta) {
+                *y += sign * c_k * d;
+            }
+            clamp(&mut y);
+            HeuristicPlayer::new(params.with_tuned(y.map(f32::exp)))
+        };
+        let openings = random_openings(start, (games + 1) / 2, 2, rng);
+        let result = play_match(start, &mut shifted(1.), &mut shifted(-1.), games, &openings);
+        let score = result.score() as f32 / games as f32;
+        for (x, d) in x.iter_mut().zip(&delta) {
+            *x += a_k * score / (2. * c_k * d);
+        }
+        clamp(&mut x);
+        if (k + 1) % 10 == 0 {
+            println!(
+                "{}/{}: {:?}",
+                k + 1,
+                iterations,
+                params.with_tuned(x.map(f32::exp))
+            );
+        }
+    }
+    params.with_tuned(x.map(f32::exp))
+}
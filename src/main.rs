@@ -11,17 +11,71 @@ extern crate flamer;
 
 // use yew::prelude::*;
 
-use std::{fs::File, io::Write};
+use std::{fs::File, io::Write, sync::Arc};
 
+use bitboard::BitboardProvider;
+use expectimax::Expectimax;
 use genetic_builder::Bot;
+use rand::{prelude::StdRng, Rng, SeedableRng};
 use grid::{Grid, VecProvider};
+use heuristic::HeuristicParams;
+use mcts::{Mcts, MctsLimits};
+use player::{
+    play_game, play_match, random_openings, CompareResult, HeuristicPlayer, HumanPlayer, Player,
+    RandomPlayer,
+};
+use pns::{Proof, ProofSearch};
+use record::GameRecord;
+use rules::Rules;
+use search::{Heuristic, SearchLimits, Searcher};
+use solver::Solver;
+use tablebase::Tablebase;
+use tournament::Tournament;
 
-use crate::grid::{CaseValue, GridProvider, PlayResult};
+use crate::grid::{CaseValue, GridProvider, MoveOutcome};
 
 mod grid;
 
+mod analysis;
+
+mod bitboard;
+
+mod expectimax;
+
+mod rules;
+
+mod search;
+
 mod genetic_builder;
 
+mod heuristic;
+
+mod mcts;
+
+mod record;
+
+mod notation;
+
+mod symmetry;
+
+mod tournament;
+
+mod perft;
+
+mod player;
+
+mod pns;
+
+mod reference;
+
+mod solver;
+
+mod tablebase;
+
+mod transposition;
+
+mod zobrist;
+
 enum Msg {
     Click(usize),
 }
@@ -50,7 +104,7 @@ impl Component for Model { */
                 self.play_result = self.grid.play(index);
 
                 if !matches!(self.play_result, PlayResult::InvalidPosition) {
-                    if self.play_result == PlayResult::Played && !self.grid.is_red_turn {
+                    if self.play_result == PlayResult::Played && !self.grid.is_red_turn() {
                         self.play_result = self.grid.play(self.grid.where_to_play());
                     }
                     true
@@ -112,73 +166,181 @@ impl Component for Model { */
     yew::start_app::<Model>();
 } */
 
-#[derive(Default, Debug)]
-pub struct CompareResult {
-    win: usize,
-    loose: usize,
-    none: usize,
+// Bot against random plays on the training grid, the training thresholds are calibrated on it
+pub fn compare_random(bot: &Bot, rng: &mut impl Rng) -> CompareResult {
+    against_random(&mut bot.clone(), rng)
 }
 
-impl CompareResult {
-    fn score(&self) -> isize {
-        self.win as isize - self.loose as isize
+// 1000 games where `player` is red after two random plays
+pub fn against_random<P>(player: &mut P, rng: &mut impl Rng) -> CompareResult
+where
+    P: Player<BitboardProvider> + ?Sized,
+{
+    let start = Grid::new(BitboardProvider::new(5, 6), Rules::default());
+    let mut random = RandomPlayer::new(rng.gen());
+    let mut result = CompareResult::default();
+    for opening in random_openings(&start, 1000, 2, rng) {
+        let mut grid = start.clone();
+        for index in opening {
+            grid.play(index).unwrap();
+        }
+        match play_game(grid, player, &mut random).status() {
+            MoveOutcome::RedWin(_) => result.win += 1,
+            MoveOutcome::BlueWin(_) => result.loose += 1,
+            _ => result.none += 1,
+        }
     }
+    result
 }
 
-pub fn compare_random(bot: &Bot) -> CompareResult {
-    let mut result = CompareResult::default();
-    for _ in 0..1000 {
-        let mut grid = Grid::new(VecProvider::new(5, 6));
-        grid.random_play();
-        grid.random_play();
-        loop {
-            match grid.play(bot.best_play(&grid, CaseValue::Red)) {
-                grid::PlayResult::InvalidPosition => {
-                    println!("Invalid position!");
-                    continue;
-                }
-                grid::PlayResult::Played => {}
-                grid::PlayResult::RedWin => {
-                    result.win += 1;
-                    break;
-                }
-                grid::PlayResult::BlueWin => {
-                    result.loose += 1;
-                    break;
-                }
-                grid::PlayResult::NobodyWin => {
-                    result.none += 1;
-                    break;
-                }
+// `random`, `heuristic`, `search:<depth>`, `mcts:<iterations>`, `expectimax:<depth>` or the number of
+// a save. The search and MCTS can be followed by `:<save>` to use that network as evaluation or
+// prior.
+fn entrant(
+    spec: &str,
+    rng: &mut impl Rng,
+    threads: usize,
+) -> Option<(String, Box<dyn Player<BitboardProvider>>)> {
+    let mut parts = spec.split(':');
+    let kind = parts.next()?;
+    let limit = parts.next();
+    let network = match parts.next() {
+        Some(n) => {
+            let n = n.parse().ok()?;
+            if !std::path::Path::new(&format!("saves/{}.json", n)).exists() {
+                return None;
+            }
+            Some(Bot::load_save(n))
+        }
+        None => None,
+    };
+    let player: Box<dyn Player<BitboardProvider>> = match (kind, limit, network) {
+        ("random", None, None) => Box::new(RandomPlayer::new(rng.gen())),
+        ("heuristic", None, None) => Box::new(HeuristicPlayer::new(HeuristicParams::load())),
+        ("search", Some(depth), network) => {
+            let limits = SearchLimits {
+                depth: depth.parse().ok()?,
+                time: None,
+            };
+            match network {
+                Some(bot) => Box::new(Searcher::new(bot, limits).with_threads(threads)),
+                None => Box::new(Searcher::new(Heuristic::new(HeuristicParams::load()), limits).with_threads(threads)),
+            }
+        }
+        ("mcts", Some(iterations), network) => {
+            let limits = MctsLimits {
+                iterations: iterations.parse().ok()?,
+                time: None,
+            };
+            let mut mcts =
+                Mcts::new(limits, std::f32::consts::SQRT_2, rng.gen()).with_threads(threads);
+            if let Some(bot) = network {
+                mcts = mcts.with_prior_bot(bot);
+            }
+            Box::new(mcts)
+        }
+        ("expectimax", Some(depth), None) => {
+            Box::new(Expectimax::new(Heuristic::new(HeuristicParams::load()), depth.parse().ok()?))
+        }
+        (n, None, None) => {
+            let n = n.parse().ok()?;
+            if !std::path::Path::new(&format!("saves/{}.json", n)).exists() {
+                return None;
             }
+            return Some((format!("saves/{}.json", n), Box::new(Bot::load_save(n))));
+        }
+        _ => return None,
+    };
+    Some((spec.to_owned(), player))
+}
 
-            match grid.random_play() {
-                grid::PlayResult::InvalidPosition => {
-                    println!("Invalid position!");
-                }
-                grid::PlayResult::Played => {}
-                grid::PlayResult::RedWin => {
-                    result.win += 1;
-                    break;
-                }
-                grid::PlayResult::BlueWin => {
-                    result.loose += 1;
-                    break;
-                }
-                grid::PlayResult::NobodyWin => {
-                    result.none += 1;
-                    break;
-                }
+// Every play of `grid` scored by the saved heuristic, the bot and a search, `played` is marked
+fn print_analysis(
+    grid: &Grid<VecProvider>,
+    bot: &Bot,
+    tablebase: &Option<Arc<Tablebase>>,
+    depth: u8,
+    played: Option<usize>,
+) {
+    let limits = SearchLimits { depth, time: None };
+    let mut searcher = Searcher::new(Heuristic::new(HeuristicParams::load()), limits);
+    if let Some(tablebase) = tablebase {
+        searcher = searcher.with_tablebase(tablebase.clone());
+    }
+    let analysis = analysis::analyse(
+        grid,
+        &HeuristicParams::load(),
+        bot,
+        &mut searcher,
+        Some(ProofSearch::new(20_000)),
+    );
+    println!("{}", analysis::table(grid, &analysis, played));
+}
+
+// Reads a saved game, the error is printed and None returned when it can't be loaded
+fn load_record(path: &str) -> Option<GameRecord> {
+    match std::fs::read_to_string(path) {
+        Ok(s) => match GameRecord::load(&s) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                println!("Invalid game: {}", e);
+                None
             }
+        },
+        Err(e) => {
+            println!("Invalid game: {}", e);
+            None
         }
     }
-    result
+}
+
+// The network only reads 5x6 grids, this tells the user when it can't play
+fn network_fits(grid: &Grid<VecProvider>) -> bool {
+    let fits = (grid.width(), grid.height()) == (5, 6);
+    if !fits {
+        println!(
+            "The network only plays 5x6 grids, choose `search`, `mcts` or `expectimax` without it"
+        );
+    }
+    fits
+}
+
+// Plays and prints how the game ended, false when the play was refused
+fn play_and_report(grid: &mut Grid<VecProvider>, index: usize) -> bool {
+    match grid.play(index) {
+        Err(e) => {
+            println!("Invalid position: {}", e);
+            return false;
+        }
+        Ok(MoveOutcome::Played) => {}
+        Ok(MoveOutcome::RedWin(_)) => println!("Red win"),
+        Ok(MoveOutcome::BlueWin(_)) => println!("Blue win"),
+        Ok(MoveOutcome::NobodyWin) => println!("Nobody win"),
+    }
+    true
 }
 
 fn main() {
-    let mut bot = Bot::new();
+    // The seed can be given as first argument to replay a session
+    let seed = std::env::args()
+        .nth(1)
+        .map(|x| x.parse().unwrap())
+        .unwrap_or_else(rand::random);
+    println!("Seed: {}", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut bot = Bot::new(&mut rng);
+    let mut bot_name = "new bot".to_owned();
+    let mut tablebase: Option<Arc<Tablebase>> = None;
+    // Plays red instead of the bot when set
+    let mut red: Option<(Box<dyn Player<VecProvider>>, &str)> = None;
+    // Red needs the network, which only reads 5x6 grids
+    let mut network_plays = true;
+    let mut blue: (Box<dyn Player<VecProvider>>, &str) =
+        (Box::new(RandomPlayer::new(rng.gen())), "random");
+    // Searches use every core unless told otherwise, one thread keeps them reproducible
+    let mut threads = rayon::current_num_threads();
     loop {
-        let mut grid = Grid::new(VecProvider::new(5, 6));
+        let mut grid = Grid::new(VecProvider::new(5, 6), Rules::default());
         loop {
             term_render(&grid);
             print!("\n\nOù jouer (X Y) : ");
@@ -191,66 +353,463 @@ fn main() {
                 let n: u32 = o.next().map(|x| x.parse().unwrap()).unwrap_or(1);
                 let mut q: f64 = o.next().map(|x| x.parse().unwrap()).unwrap_or(0.5);
                 for _ in 0..n {
-                    bot = bot.evolve(q);
+                    bot = bot.evolve(q, &mut rng);
                     q *= 0.75;
                     println!("Testing against random player...");
                 }
                 continue;
             }
             if input.trim().starts_with("load ") {
-                bot = Bot::load_save(input.trim()[5..].trim().parse().unwrap());
+                let n = input.trim()[5..].trim().parse().unwrap();
+                bot = Bot::load_save(n);
+                bot_name = format!("saves/{}.json", n);
                 println!("Loaded save");
                 continue;
             }
-            if input.contains("test") {
-                println!("Testing against random player...");
-                println!("{:?}", compare_random(&bot));
+            if input.trim().starts_with("replay ") {
+                let record = match load_record(input.trim()[7..].trim()) {
+                    Some(record) => record,
+                    None => continue,
+                };
+                println!("{} (red) vs {} (blue)", record.red, record.blue);
+                if let Err(e) = record.replay(VecProvider::new(record.width, record.height)) {
+                    println!("Replay failed: {}", e);
+                    continue;
+                }
+                let mut replay = record.start(VecProvider::new(record.width, record.height)).unwrap();
+                term_render(&replay);
+                for index in &record.moves {
+                    println!("{:?}", replay.play(*index).unwrap());
+                    term_render(&replay);
+                }
+                continue;
+            }
+            if input.trim() == "analyse" || input.trim().starts_with("analyse ") {
+                // analyse [depth], analyse position <notation> [depth] or analyse game <path> [play],
+                // a game without a play number shows every play of the loser
+                let args = input.trim()[7..].split_whitespace().collect::<Vec<_>>();
+                match args.as_slice() {
+                    ["position", position, rest @ ..] => match position.parse() {
+                        Ok(position) => {
+                            let depth = rest.first().map_or(6, |x| x.parse().unwrap());
+                            print_analysis(&position, &bot, &tablebase, depth, None);
+                        }
+                        Err(e) => println!("Invalid position: {}", e),
+                    },
+                    ["game", path, rest @ ..] => {
+                        let record = match load_record(path) {
+                            Some(record) => record,
+                            None => continue,
+                        };
+                        let provider = || VecProvider::new(record.width, record.height);
+                        if let Err(e) = record.replay(provider()) {
+                            println!("Replay failed: {}", e);
+                            continue;
+                        }
+                        let only = rest.first().map(|x| x.parse::<usize>().unwrap() - 1);
+                        let loser_is_red = match record.result {
+                            MoveOutcome::RedWin(_) => Some(false),
+                            MoveOutcome::BlueWin(_) => Some(true),
+                            _ => None,
+                        };
+                        let mut position = record.start(provider()).unwrap();
+                        for (ply, index) in record.moves.iter().enumerate() {
+                            let shown = match only {
+                                Some(only) => only == ply,
+                                None => loser_is_red.map_or(true, |x| x == position.is_red_turn()),
+                            };
+                            if shown {
+                                println!(
+                                    "\nPlay {} by {}",
+                                    ply + 1,
+                                    if position.is_red_turn() { "red" } else { "blue" }
+                                );
+                                term_render(&position);
+                                println!();
+                                print_analysis(&position, &bot, &tablebase, 6, Some(*index));
+                            }
+                            position.play(*index).unwrap();
+                        }
+                    }
+                    rest => {
+                        let depth = rest.first().map_or(6, |x| x.parse().unwrap());
+                        print_analysis(&grid, &bot, &tablebase, depth, None);
+                    }
+                }
+                continue;
+            }
+            if input.trim().starts_with("perft ") {
+                // perft <width> <height> <depth> [walls...]
+                let args = input.trim()[6..]
+                    .split_whitespace()
+                    .map(|x| x.parse::<usize>().unwrap())
+                    .collect::<Vec<_>>();
+                let mut position = Grid::new(VecProvider::new(args[0], args[1]), Rules::default());
+                for wall in &args[3..] {
+                    position.set(*wall, CaseValue::Black);
+                }
+                for (depth, level) in position.perft(args[2]).iter().enumerate() {
+                    println!("{} {:?}", depth + 1, level);
+                }
                 continue;
             }
-            //let mut input = input.trim().split(" ").map(|x| x.parse().unwrap());
-            //let pos = grid.x_y_to_index(input.next().unwrap(), input.next().unwrap());
-            match grid.play(bot.best_play(&grid, CaseValue::Red)) {
-                grid::PlayResult::InvalidPosition => {
-                    println!("Invalid position!");
+            if input.trim() == "solve" || input.trim().starts_with("solve ") {
+                // solve [<width> <height> [walls...]], the current position without arguments
+                let args = input.trim()[5..]
+                    .split_whitespace()
+                    .map(|x| x.parse::<usize>().unwrap())
+                    .collect::<Vec<_>>();
+                let mut position = if args.len() >= 2 {
+                    let mut position =
+                        Grid::new(VecProvider::new(args[0], args[1]), Rules::default());
+                    for wall in &args[2..] {
+                        position.set(*wall, CaseValue::Black);
+                    }
+                    position
+                } else {
+                    grid.clone()
+                };
+                let mut solver = Solver::new();
+                let solution = solver.solve(&mut position);
+                println!(
+                    "{:?} in {} plays for {}, best play {:?} ({} positions)",
+                    solution.value,
+                    solution.distance,
+                    if position.is_red_turn() { "red" } else { "blue" },
+                    solution.best_move,
+                    solver.nodes
+                );
+                if !position.is_over() {
+                    let index = position.where_to_play(&HeuristicParams::load());
+                    println!(
+                        "Heuristic plays {} for {:?}",
+                        index,
+                        solver.grade(&position, index)
+                    );
+                }
+                continue;
+            }
+            if input.trim().starts_with("tablebase load ") {
+                match Tablebase::load(input.trim()[15..].trim()) {
+                    Ok(loaded) => {
+                        println!(
+                            "Loaded {} positions with up to {} empty cases",
+                            loaded.len(),
+                            loaded.max_empty()
+                        );
+                        let loaded = Arc::new(loaded);
+                        bot = bot.with_tablebase(loaded.clone());
+                        tablebase = Some(loaded);
+                    }
+                    Err(e) => println!("Invalid tablebase: {}", e),
+                }
+                continue;
+            }
+            if input.trim().starts_with("tablebase ") {
+                // tablebase <width> <height> <max empty cases> [walls...]
+                let args = input.trim()[10..]
+                    .split_whitespace()
+                    .map(|x| x.parse::<usize>().unwrap())
+                    .collect::<Vec<_>>();
+                let mut position = Grid::new(VecProvider::new(args[0], args[1]), Rules::default());
+                for wall in &args[3..] {
+                    position.set(*wall, CaseValue::Black);
+                }
+                let generated = Tablebase::generate(&position, args[2]);
+                std::fs::create_dir_all("tablebases").unwrap();
+                let path = format!("tablebases/{}x{}-{}.bin", args[0], args[1], args[2]);
+                generated.save(&path).unwrap();
+                println!("{} positions saved in `{}`", generated.len(), path);
+                let generated = Arc::new(generated);
+                bot = bot.with_tablebase(generated.clone());
+                tablebase = Some(generated);
+                continue;
+            }
+            if input.trim().starts_with("diff ") {
+                // diff <games>, checks every provider against the reference rules engine
+                let games = input.trim()[5..].trim().parse().unwrap();
+                for (name, result) in [
+                    ("VecProvider", reference::differential::<VecProvider>(games, &mut rng)),
+                    (
+                        "BitboardProvider",
+                        reference::differential::<BitboardProvider>(games, &mut rng),
+                    ),
+                ] {
+                    match result {
+                        Ok(()) => println!("{}: no divergence in {} games", name, games),
+                        Err(e) => println!("{}: diverged at {}", name, e),
+                    }
+                }
+                continue;
+            }
+            if input.trim() == "think" || input.trim().starts_with("think ") {
+                // think [depth] [milliseconds]
+                let mut args = input.trim()[5..].split_whitespace();
+                let limits = SearchLimits {
+                    depth: args.next().map(|x| x.parse().unwrap()).unwrap_or(12),
+                    time: args
+                        .next()
+                        .map(|x| std::time::Duration::from_millis(x.parse().unwrap())),
+                };
+                let mut searcher = Searcher::new(Heuristic::new(HeuristicParams::load()), limits).with_threads(threads);
+                if let Some(tablebase) = &tablebase {
+                    searcher = searcher.with_tablebase(tablebase.clone());
+                }
+                let result = searcher.search(&grid);
+                println!(
+                    "Depth {} score {} best play {:?} pv {:?} ({} nodes)",
+                    result.depth, result.score, result.best_move, result.pv, result.nodes
+                );
+                continue;
+            }
+            if input.trim() == "prove" || input.trim().starts_with("prove ") {
+                // prove [nodes], looks for a forced win and for the plays that lose by force
+                let nodes = input.trim()[5..].trim().parse().unwrap_or(1_000_000);
+                let proof_search = ProofSearch::new(nodes);
+                let result = proof_search.prove(&grid);
+                match (result.proof, &result.tree) {
+                    (Proof::Win, Some(tree)) => println!(
+                        "Forced win in at most {} plays for {}, line {:?} ({} nodes)",
+                        tree.depth(),
+                        if grid.is_red_turn() { "red" } else { "blue" },
+                        tree.main_line(),
+                        result.nodes
+                    ),
+                    (proof, _) => println!("{:?} ({} nodes)", proof, result.nodes),
+                }
+                for (index, plays) in proof_search.losing_moves(&grid) {
+                    println!("{} is a forced loss in at most {} plays", index, plays);
+                }
+                continue;
+            }
+            if input.trim().starts_with("avoid ") {
+                // avoid <nodes>, the bot proves which plays lose before choosing
+                let nodes = input.trim()[6..].trim().parse().unwrap();
+                bot = bot.with_proof_search(ProofSearch::new(nodes));
+                println!("The bot avoids the plays proven to lose");
+                continue;
+            }
+            if input.trim().starts_with("threads ") {
+                threads = input.trim()[8..].trim().parse().unwrap();
+                println!("Searches use {} threads", threads);
+                continue;
+            }
+            if input.trim() == "search off" || input.trim() == "mcts off" {
+                red = None;
+                network_plays = true;
+                println!("The bot plays again");
+                continue;
+            }
+            if input.trim() == "human" {
+                blue = (Box::new(HumanPlayer), "human");
+                println!("You play blue");
+                continue;
+            }
+            if input.trim() == "heuristic" {
+                blue = (Box::new(HeuristicPlayer::new(HeuristicParams::load())), "heuristic");
+                println!("The heuristic plays blue");
+                continue;
+            }
+            if input.trim() == "random" {
+                blue = (Box::new(RandomPlayer::new(rng.gen())), "random");
+                println!("Random plays blue");
+                continue;
+            }
+            if input.trim().starts_with("mcts ") {
+                // mcts <iterations> [milliseconds] [nn] [prior], nn makes the bot guide the rollouts
+                // and prior makes it choose which plays to explore
+                let args = input.trim()[5..].split_whitespace().collect::<Vec<_>>();
+                let uses_network = args.contains(&"nn") || args.contains(&"prior");
+                if uses_network && !network_fits(&grid) {
                     continue;
                 }
-                grid::PlayResult::Played => {}
-                grid::PlayResult::RedWin => {
-                    println!("Red win");
-                    break;
+                let limits = MctsLimits {
+                    iterations: args[0].parse().unwrap(),
+                    time: args
+                        .get(1)
+                        .filter(|x| **x != "nn" && **x != "prior")
+                        .map(|x| std::time::Duration::from_millis(x.parse().unwrap())),
+                };
+                let mut new =
+                    Mcts::new(limits, std::f32::consts::SQRT_2, rng.gen()).with_threads(threads);
+                if args.contains(&"nn") {
+                    new = new.with_rollout_bot(bot.clone());
                 }
-                grid::PlayResult::BlueWin => {
-                    println!("Blue win");
-                    break;
+                if args.contains(&"prior") {
+                    new = new.with_prior_bot(bot.clone());
                 }
-                grid::PlayResult::NobodyWin => {
-                    println!("Nobody win");
-                    break;
+                let result = new.search(&grid);
+                println!(
+                    "Best play {:?} won {:.1}% of {} visits ({} iterations)",
+                    result.best_move,
+                    result.win_rate * 100.,
+                    result.visits,
+                    result.iterations
+                );
+                red = Some((Box::new(new), "mcts"));
+                network_plays = uses_network;
+                println!("MCTS plays instead of the bot");
+                continue;
+            }
+            if input.trim().starts_with("search ") {
+                // search <depth> [milliseconds] [nn], nn evaluates the leaves with the bot
+                let args = input.trim()[7..].split_whitespace().collect::<Vec<_>>();
+                let limits = SearchLimits {
+                    depth: args[0].parse().unwrap(),
+                    time: args
+                        .get(1)
+                        .filter(|x| **x != "nn")
+                        .map(|x| std::time::Duration::from_millis(x.parse().unwrap())),
+                };
+                let new: Box<dyn Player<VecProvider>> = if args.contains(&"nn") {
+                    let mut new = Searcher::new(bot.clone(), limits).with_threads(threads);
+                    if let Some(tablebase) = &tablebase {
+                        new = new.with_tablebase(tablebase.clone());
+                    }
+                    Box::new(new)
+                } else {
+                    let mut new = Searcher::new(Heuristic::new(HeuristicParams::load()), limits).with_threads(threads);
+                    if let Some(tablebase) = &tablebase {
+                        new = new.with_tablebase(tablebase.clone());
+                    }
+                    Box::new(new)
+                };
+                red = Some((new, "search"));
+                network_plays = args.contains(&"nn");
+                println!("The search plays instead of the bot");
+                continue;
+            }
+            if input.trim().starts_with("tune ") {
+                // tune <iterations> [games per iteration], starts from the saved parameters
+                let mut args = input.trim()[5..].split_whitespace();
+                let iterations = args.next().unwrap().parse().unwrap();
+                let games = args.next().map_or(20, |x| x.parse().unwrap());
+                let start = Grid::new(BitboardProvider::new(5, 6), Rules::default());
+                let before = HeuristicParams::load();
+                let tuned = heuristic::tune(&start, before, iterations, games, &mut rng);
+                let openings = random_openings(&start, 100, 2, &mut rng);
+                let result = play_match(
+                    &start,
+                    &mut HeuristicPlayer::new(tuned),
+                    &mut HeuristicPlayer::new(before),
+                    200,
+                    &openings,
+                );
+                println!("Tuned against previous: {:?}", result);
+                if result.score() > 0 {
+                    tuned.save();
+                } else {
+                    println!("Previous parameters kept");
                 }
+                continue;
             }
-
-            match grid.random_play() {
-                grid::PlayResult::InvalidPosition => {
-                    println!("Invalid position!");
+            if input.trim().starts_with("expectimax ") {
+                // expectimax <depth>, compares it with the bot on the `compare_random` games
+                let depth = input.trim()[11..].trim().parse().unwrap();
+                let mut expectimax = Expectimax::new(Heuristic::new(HeuristicParams::load()), depth);
+                let start = Grid::new(VecProvider::new(5, 6), Rules::default());
+                let (score, index) = expectimax.solve(&start);
+                if expectimax.exact {
+                    println!("Expected score {:.3} playing {:?}", score, index);
+                } else {
+                    println!("Heuristic estimate {:.3} playing {:?}", score, index);
                 }
-                grid::PlayResult::Played => {}
-                grid::PlayResult::RedWin => {
-                    println!("Red win");
-                    break;
+                let result = against_random(&mut expectimax, &mut rng);
+                println!("Expectimax: {:?} score {}", result, result.score());
+                let result = compare_random(&bot, &mut rng);
+                println!("{}: {:?} score {}", bot_name, result, result.score());
+                red = Some((Box::new(Expectimax::new(Heuristic::new(HeuristicParams::load()), depth)), "expectimax"));
+                network_plays = false;
+                println!("Expectimax plays instead of the bot");
+                continue;
+            }
+            if input.trim() == "ratings" {
+                for (name, rating) in tournament::load_ratings() {
+                    println!(
+                        "{}: {:.0} ± {:.0} ({} games)",
+                        name, rating.elo, rating.interval, rating.games
+                    );
                 }
-                grid::PlayResult::BlueWin => {
-                    println!("Blue win");
-                    break;
+                continue;
+            }
+            if input.trim().starts_with("tournament ") {
+                // tournament <games per pair> <players...>, `saves` adds every save
+                let mut args = input.trim()[11..].split_whitespace();
+                let games = args.next().unwrap().parse().unwrap();
+                let mut specs = Vec::new();
+                for spec in args {
+                    if spec == "saves" {
+                        let count = std::fs::read_dir("saves").map_or(0, |x| x.count());
+                        specs.extend((0..count).map(|x| x.to_string()));
+                    } else {
+                        specs.push(spec.to_owned());
+                    }
                 }
-                grid::PlayResult::NobodyWin => {
-                    println!("Nobody win");
-                    break;
+                let mut entrants = Vec::new();
+                for spec in &specs {
+                    match entrant(spec, &mut rng, threads) {
+                        Some(x) => entrants.push(x),
+                        None => println!("Unknown player `{}`", spec),
+                    }
                 }
+                let start = Grid::new(BitboardProvider::new(5, 6), Rules::default());
+                let tournament = Tournament::run(&start, &mut entrants, games, &mut rng);
+                println!("{}", tournament.crosstable());
+                tournament.save();
+                continue;
+            }
+            if input.trim() == "position" {
+                println!("{}", grid.to_notation());
+                continue;
+            }
+            if input.trim().starts_with("position ") {
+                match input.trim()[9..].trim().parse() {
+                    Ok(position) => grid = position,
+                    Err(e) => println!("Invalid position: {}", e),
+                }
+                continue;
+            }
+            if input.trim() == "undo" {
+                // Take back both the random play and the bot play
+                grid.undo();
+                grid.undo();
+                println!("Took back last move");
+                continue;
+            }
+            if input.contains("test") {
+                println!("Testing against random player...");
+                println!("{:?}", compare_random(&bot, &mut rng));
+                continue;
+            }
+            if network_plays && !network_fits(&grid) {
+                continue;
+            }
+            let index = match &mut red {
+                Some((player, _)) => player.choose_move(&grid, CaseValue::Red),
+                None => bot.choose_move(&grid, CaseValue::Red),
+            };
+            if !play_and_report(&mut grid, index) {
+                continue;
+            }
+            if !grid.is_over() {
+                let index = blue.0.choose_move(&grid, CaseValue::Blue);
+                play_and_report(&mut grid, index);
+            }
+            if grid.is_over() {
+                term_render(&grid);
+                let red_name = red.as_ref().map_or(bot_name.as_str(), |x| x.1);
+                GameRecord::from_grid(&grid, red_name, blue.1).auto_save();
+                break;
             }
         }
     }
 }
 fn term_render(grid: &Grid<VecProvider>) {
+    // The line that ended the game is drawn in lowercase
+    let line: &[usize] = match grid.status() {
+        MoveOutcome::RedWin(line) | MoveOutcome::BlueWin(line) => &line.cells,
+        _ => &[],
+    };
     for (index, case) in grid.cases.iter().enumerate() {
         if index % grid.width() == 0 {
             print!("\n");
@@ -258,6 +817,8 @@ fn term_render(grid: &Grid<VecProvider>) {
         print!(
             "{}",
             match case {
+                grid::CaseValue::Red if line.contains(&index) => 'r',
+                grid::CaseValue::Blue if line.contains(&index) => 'b',
                 grid::CaseValue::Red => 'R',
                 grid::CaseValue::Blue => 'B',
                 grid::CaseValue::Yellow => '-',
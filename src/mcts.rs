@@ -0,0 +1,282 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use rand::{prelude::StdRng, Rng, SeedableRng};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    genetic_builder::Bot,
+    grid::{CaseValue, Grid, GridProvider, MoveOutcome},
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct MctsLimits {
+    pub iterations: usize,
+    // The search stops at whichever limit comes first
+    pub time: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+pub struct MctsResult {
+    pub best_move: Option<usize>,
+    // Visits of the best play and the share of them that the side to move won
+    pub visits: u32,
+    pub win_rate: f32,
+    pub iterations: usize,
+}
+
+struct Node {
+    index: usize,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    // Plays not expanded yet with their prior
+    untried: Vec<(usize, f32)>,
+    prior: f32,
+    visits: u32,
+    // Sum of the results for the player who played `index`, a draw counts half
+    wins: f32,
+    is_red: bool,
+}
+
+// Upper confidence bounds applied to trees, with random or network guided rollouts. With a prior
+// network the most likely plays are expanded first and the selection follows PUCT instead.
+pub struct Mcts {
+    pub limits: MctsLimits,
+    pub exploration: f32,
+    rollout_bot: Option<Bot>,
+    prior_bot: Option<Bot>,
+    threads: usize,
+    rng: StdRng,
+}
+
+impl Mcts {
+    pub fn new(limits: MctsLimits, exploration: f32, seed: u64) -> Self {
+        Self {
+            limits,
+            exploration,
+            rollout_bot: None,
+            prior_bot: None,
+            threads: 1,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    // Rollouts follow the bot plays instead of random ones, the bot only knows 5x6 grids
+    pub fn with_rollout_bot(self, bot: Bot) -> Self {
+        Self {
+            rollout_bot: Some(bot),
+            ..self
+        }
+    }
+
+    // The priors are a softmax of the network scores of the plays, the bot only knows 5x6 grids
+    pub fn with_prior_bot(self, bot: Bot) -> Self {
+        Self {
+            prior_bot: Some(bot),
+            ..self
+        }
+    }
+
+    // Each thread grows its own tree and their root statistics are added up, the iterations are
+    // shared between the trees
+    pub fn with_threads(self, threads: usize) -> Self {
+        Self {
+            threads: threads.max(1),
+            ..self
+        }
+    }
+
+    pub fn best_play<T: GridProvider + Clone + Sync>(&mut self, grid: &Grid<T>) -> usize {
+        self.search(grid).best_move.unwrap()
+    }
+
+    pub fn search<T: GridProvider + Clone + Sync>(&mut self, grid: &Grid<T>) -> MctsResult {
+        let deadline = self.limits.time.map(|x| Instant::now() + x);
+        let iterations = (self.limits.iterations + self.threads - 1) / self.threads;
+        // Seeds are drawn up front so a seed gives the same trees whatever the scheduling
+        let seeds = (0..self.threads)
+            .map(|_| self.rng.gen())
+            .collect::<Vec<u64>>();
+        let trees = if self.threads == 1 {
+            vec![self.grow(grid, iterations, deadline, seeds[0])]
+        } else {
+            seeds
+                .into_par_iter()
+                .map(|seed| self.grow(grid, iterations, deadline, seed))
+                .collect()
+        };
+        let mut roots = BTreeMap::new();
+        for (children, _) in &trees {
+            for (index, visits, wins) in children {
+                let root = roots.entry(*index).or_insert((0, 0.));
+                root.0 += visits;
+                root.1 += wins;
+            }
+        }
+        let mut best: Option<(usize, u32, f32)> = None;
+        for (index, (visits, wins)) in roots {
+            if best.map_or(true, |x| visits > x.1) {
+                best = Some((index, visits, wins));
+            }
+        }
+        MctsResult {
+            best_move: best
+                .map(|x| x.0)
+                .or_else(|| grid.legal_moves().next().filter(|_| !grid.is_over())),
+            visits: best.map_or(0, |x| x.1),
+            win_rate: best.map_or(0., |x| x.2 / x.1 as f32),
+            iterations: trees.iter().map(|x| x.1).sum(),
+        }
+    }
+
+    // Plays, visits and wins of the root children and the number of iterations done
+    fn grow<T: GridProvider + Clone>(
+        &self,
+        grid: &Grid<T>,
+        max_iterations: usize,
+        deadline: Option<Instant>,
+        seed: u64,
+    ) -> (Vec<(usize, u32, f32)>, usize) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut grid = grid.clone();
+        let mut nodes = vec![Node {
+            index: 0,
+            parent: None,
+            children: Vec::new(),
+            untried: self.untried(&mut grid),
+            prior: 1.,
+            visits: 0,
+            wins: 0.,
+            is_red: !grid.is_red_turn(),
+        }];
+        let mut iterations = 0;
+        while iterations < max_iterations
+            && (!nodes[0].untried.is_empty() || !nodes[0].children.is_empty())
+            && deadline.map_or(true, |x| Instant::now() < x)
+        {
+            let mut node = 0;
+            let mut plays = 0;
+            // Selection
+            while nodes[node].untried.is_empty() && !nodes[node].children.is_empty() {
+                node = self.select(&nodes, node);
+                grid.play(nodes[node].index).unwrap();
+                plays += 1;
+            }
+            // Expansion
+            if !nodes[node].untried.is_empty() {
+                let untried = &mut nodes[node].untried;
+                let (index, prior) = if self.prior_bot.is_some() {
+                    untried.pop().unwrap()
+                } else {
+                    untried.swap_remove(rng.gen_range(0..untried.len()))
+                };
+                let is_red = grid.is_red_turn();
+                grid.play(index).unwrap();
+                plays += 1;
+                nodes.push(Node {
+                    index,
+                    parent: Some(node),
+                    children: Vec::new(),
+                    untried: self.untried(&mut grid),
+                    prior,
+                    visits: 0,
+                    wins: 0.,
+                    is_red,
+                });
+                let child = nodes.len() - 1;
+                nodes[node].children.push(child);
+                node = child;
+            }
+            // Simulation
+            while !grid.is_over() {
+                match &self.rollout_bot {
+                    Some(bot) => {
+                        let color = if grid.is_red_turn() {
+                            CaseValue::Red
+                        } else {
+                            CaseValue::Blue
+                        };
+                        grid.play(bot.best_play(&grid, color)).unwrap();
+                    }
+                    None => {
+                        grid.random_play(&mut rng).unwrap();
+                    }
+                }
+                plays += 1;
+            }
+            let red_result = match grid.status() {
+                MoveOutcome::RedWin(_) => 1.,
+                MoveOutcome::BlueWin(_) => 0.,
+                _ => 0.5,
+            };
+            // Backpropagation
+            let mut current = Some(node);
+            while let Some(x) = current {
+                nodes[x].visits += 1;
+                nodes[x].wins += if nodes[x].is_red {
+                    red_result
+                } else {
+                    1. - red_result
+                };
+                current = nodes[x].parent;
+            }
+            for _ in 0..plays {
+                grid.undo();
+            }
+            iterations += 1;
+        }
+        let children = nodes[0]
+            .children
+            .iter()
+            .map(|x| (nodes[*x].index, nodes[*x].visits, nodes[*x].wins))
+            .collect();
+        (children, iterations)
+    }
+
+    // Legal plays with their prior, the most likely one last
+    fn untried<T: GridProvider + Clone>(&self, grid: &mut Grid<T>) -> Vec<(usize, f32)> {
+        if grid.is_over() {
+            return Vec::new();
+        }
+        let mut plays = grid.legal_moves().map(|x| (x, 1.)).collect::<Vec<_>>();
+        if let Some(bot) = &self.prior_bot {
+            let color = if grid.is_red_turn() {
+                CaseValue::Red
+            } else {
+                CaseValue::Blue
+            };
+            for play in &mut plays {
+                grid.play(play.0).unwrap();
+                play.1 = bot.execute(grid, color);
+                grid.undo();
+            }
+            // The lowest score is the best play, it is subtracted so the exponentials can't vanish
+            let best = plays.iter().map(|x| x.1).fold(f32::INFINITY, f32::min);
+            plays.iter_mut().for_each(|x| x.1 = (best - x.1).exp());
+            let sum = plays.iter().map(|x| x.1).sum::<f32>();
+            plays.iter_mut().for_each(|x| x.1 /= sum);
+            plays.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        }
+        plays
+    }
+
+    fn select(&self, nodes: &[Node], node: usize) -> usize {
+        let log_visits = (nodes[node].visits as f32).ln();
+        let sqrt_visits = (nodes[node].visits as f32).sqrt();
+        let uct = |x: &Node| {
+            let exploration = if self.prior_bot.is_some() {
+                x.prior * sqrt_visits / (1. + x.visits as f32)
+            } else {
+                (log_visits / x.visits as f32).sqrt()
+            };
+            x.wins / x.visits as f32 + self.exploration * exploration
+        };
+        *nodes[node]
+            .children
+            .iter()
+            .max_by(|a, b| uct(&nodes[**a]).partial_cmp(&uct(&nodes[**b])).unwrap())
+            .unwrap()
+    }
+}
@@ -0,0 +1,195 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+    grid::{CaseValue, Grid, GridProvider},
+    rules::Rules,
+};
+
+// A position is written `<width>x<height>:<rows>:<turn>:<last play>`, for instance
+// `3x3:R--/-B-/...:r:4`. Rows are separated by `/` and use `R` and `B` for stones, `-` for
+// playable cases, `.` for other empty cases and `#` for walls. The turn is `r` or `b` and the last
+// play is a case index or `-` before the first play. The playable cases follow from the last play,
+// so when parsing `-` and `.` are both read as empty cases.
+
+#[derive(Debug, PartialEq)]
+pub enum NotationError {
+    InvalidFormat,
+    InvalidSize,
+    InvalidCase(char),
+    InvalidTurn,
+    InvalidLastPlay,
+}
+
+impl Display for NotationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidFormat => write!(f, "expected `<width>x<height>:<rows>:<turn>:<last>`"),
+            Self::InvalidSize => write!(f, "the rows don't match the grid size or it is too large"),
+            Self::InvalidCase(c) => write!(f, "unknown case `{}`", c),
+            Self::InvalidTurn => write!(f, "the turn must be `r` or `b`"),
+            Self::InvalidLastPlay => write!(f, "the last play must be a stone of the other side"),
+        }
+    }
+}
+
+impl CaseValue {
+    pub fn to_notation(&self) -> char {
+        match self {
+            CaseValue::Red => 'R',
+            CaseValue::Blue => 'B',
+            CaseValue::Yellow => '-',
+            CaseValue::White => '.',
+            CaseValue::Black => '#',
+        }
+    }
+
+    pub fn from_notation(c: char) -> Result<Self, NotationError> {
+        match c {
+            'R' => Ok(CaseValue::Red),
+            'B' => Ok(CaseValue::Blue),
+            '-' => Ok(CaseValue::Yellow),
+            '.' => Ok(CaseValue::White),
+            '#' => Ok(CaseValue::Black),
+            c => Err(NotationError::InvalidCase(c)),
+        }
+    }
+}
+
+impl<T: GridProvider + Clone> Grid<T> {
+    pub fn to_notation(&self) -> String {
+        let rows = self
+            .cases
+            .iter()
+            .map(|x| x.to_notation())
+            .collect::<Vec<_>>()
+            .chunks(self.width())
+            .map(|x| x.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join("/");
+        format!(
+            "{}x{}:{}:{}:{}",
+            self.width(),
+            self.height(),
+            rows,
+            if self.is_red_turn() { 'r' } else { 'b' },
+            self.last_play()
+                .map(|x| x.to_string())
+                .unwrap_or_else(|| "-".to_owned())
+        )
+    }
+
+    pub fn parse_notation(s: &str, rules: Rules) -> Result<Self, NotationError> {
+        let parts = s.trim().split(':').collect::<Vec<_>>();
+        if parts.len() != 4 {
+            return Err(NotationError::InvalidFormat);
+        }
+        let mut size = parts[0].split('x').map(|x| x.parse::<usize>());
+        let (width, height) = match (size.next(), size.next(), size.next()) {
+            (Some(Ok(width)), Some(Ok(height)), None) if width > 0 && height > 0 => (width, height),
+            _ => return Err(NotationError::InvalidFormat),
+        };
+        let rows = parts[1].split('/').collect::<Vec<_>>();
+        if rows.len() != height || rows.iter().any(|x| x.chars().count() != width) {
+            return Err(NotationError::InvalidSize);
+        }
+        let mut cases = T::with_size(width, height).ok_or(NotationError::InvalidSize)?;
+        for (index, c) in rows.iter().flat_map(|x| x.chars()).enumerate() {
+            cases.set(index, CaseValue::from_notation(c)?);
+        }
+        let is_red_turn = match parts[2] {
+            "r" => true,
+            "b" => false,
+            _ => return Err(NotationError::InvalidTurn),
+        };
+        let last_play = match parts[3] {
+            "-" => None,
+            x => match x.parse::<usize>().ok().and_then(|x| Some((x, cases.get(x)?))) {
+                // The last play was made by the side that isn't to move
+                Some((x, CaseValue::Red)) if !is_red_turn => Some(x),
+                Some((x, CaseValue::Blue)) if is_red_turn => Some(x),
+                _ => return Err(NotationError::InvalidLastPlay),
+            },
+        };
+        Ok(Self::from_position(cases, rules, is_red_turn, last_play))
+    }
+}
+
+impl<T: GridProvider + Clone> FromStr for Grid<T> {
+    type Err = NotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_notation(s, Rules::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bitboard::BitboardProvider, grid::VecProvider};
+    use rand::{prelude::StdRng, Rng, SeedableRng};
+
+    fn parse<T: GridProvider + Clone>(s: &str) -> Result<Grid<T>, NotationError> {
+        Grid::parse_notation(s, Rules::default())
+    }
+
+    // Every position of random games is parsed back to the same position, and a play then an undo
+    // on the parsed grid gets back to it too
+    fn round_trip<T: GridProvider + Clone>(provider: T, rng: &mut StdRng) {
+        for _ in 0..200 {
+            let mut grid = Grid::new(provider.clone(), Rules::default());
+            grid.place_blocks(rng.gen_range(0..3), rng);
+            loop {
+                let notation = grid.to_notation();
+                let mut parsed = parse::<T>(&notation).unwrap();
+                assert_eq!(parsed.to_notation(), notation);
+                assert_eq!(parsed.hash(), grid.hash(), "{}", notation);
+                assert_eq!(parsed.status(), grid.status(), "{}", notation);
+                if grid.is_over() {
+                    break;
+                }
+                for index in grid.legal_moves() {
+                    parsed.play(index).unwrap();
+                    parsed.undo();
+                    assert_eq!(parsed.to_notation(), notation);
+                    assert_eq!(parsed.hash(), grid.hash());
+                }
+                grid.random_play(rng).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_random_games() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for (width, height) in [(3, 3), (4, 4), (5, 6), (3, 5)] {
+            round_trip(BitboardProvider::new(width, height), &mut rng);
+            round_trip(VecProvider::new(width, height), &mut rng);
+        }
+    }
+
+    #[test]
+    fn playable_cases_follow_last_play() {
+        let grid = parse::<VecProvider>("3x3:R../.../..-:b:0").unwrap();
+        assert_eq!(grid.legal_moves().collect::<Vec<_>>(), [1, 3, 4]);
+        assert_eq!(grid.to_notation(), "3x3:R-./--./...:b:0");
+    }
+
+    #[test]
+    fn last_play_of_side_to_move() {
+        assert_eq!(
+            parse::<VecProvider>("3x3:R--/---/---:r:0").err(),
+            Some(NotationError::InvalidLastPlay)
+        );
+        assert!(parse::<VecProvider>("3x3:R--/---/---:b:0").is_ok());
+    }
+
+    #[test]
+    fn size_too_large_for_bitboard() {
+        let notation = format!("10x12:{}:r:-", ["----------"; 12].join("/"));
+        assert_eq!(
+            parse::<BitboardProvider>(&notation).err(),
+            Some(NotationError::InvalidSize)
+        );
+        assert!(parse::<VecProvider>(&notation).is_ok());
+    }
+}
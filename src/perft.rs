@@ -0,0 +1,117 @@
+use crate::grid::{Grid, GridProvider, MoveOutcome};
+
+// Plays reached at one depth of the game tree. Outcomes are named after `MoveOutcome` so with the
+// default misère rules `red_wins` counts the games where blue completed a line.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PerftLevel {
+    pub nodes: u64,
+    pub red_wins: u64,
+    pub blue_wins: u64,
+    pub draws: u64,
+}
+
+impl<T: GridProvider + Clone> Grid<T> {
+    // Counts for every depth from 1 to `depth`, finished games are not explored further
+    pub fn perft(&mut self, depth: usize) -> Vec<PerftLevel> {
+        let mut levels = vec![PerftLevel::default(); depth];
+        self.perft_level(&mut levels);
+        levels
+    }
+
+    fn perft_level(&mut self, levels: &mut [PerftLevel]) {
+        if levels.is_empty() || self.is_over() {
+            return;
+        }
+        let mut next = self.next_legal_move(0);
+        while let Some(index) = next {
+            let level = &mut levels[0];
+            level.nodes += 1;
+            match self.play(index).unwrap() {
+                MoveOutcome::Played => self.perft_level(&mut levels[1..]),
+                MoveOutcome::RedWin(_) => level.red_wins += 1,
+                MoveOutcome::BlueWin(_) => level.blue_wins += 1,
+                MoveOutcome::NobodyWin => level.draws += 1,
+            }
+            self.undo();
+            next = self.next_legal_move(index + 1);
+        }
+    }
+}
+
+// Counts with the default rules and no walls, any change to them means the rules changed
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bitboard::BitboardProvider, grid::VecProvider, rules::Rules};
+
+    fn level(nodes: u64, red_wins: u64, blue_wins: u64, draws: u64) -> PerftLevel {
+        PerftLevel {
+            nodes,
+            red_wins,
+            blue_wins,
+            draws,
+        }
+    }
+
+    fn check(width: usize, height: usize, expected: &[PerftLevel]) {
+        let mut grid = Grid::new(BitboardProvider::new(width, height), Rules::default());
+        assert_eq!(grid.perft(expected.len()), expected);
+        let mut grid = Grid::new(VecProvider::new(width, height), Rules::default());
+        assert_eq!(grid.perft(expected.len()), expected);
+    }
+
+    #[test]
+    fn perft_3x3() {
+        check(
+            3,
+            3,
+            &[
+                level(9, 0, 0, 0),
+                level(40, 0, 0, 0),
+                level(160, 0, 0, 0),
+                level(496, 0, 0, 0),
+                level(1328, 0, 176, 0),
+                level(2592, 376, 0, 0),
+                level(4264, 0, 976, 0),
+                level(5032, 1000, 0, 0),
+                level(4032, 0, 1656, 2376),
+            ],
+        );
+    }
+
+    #[test]
+    fn perft_4x4() {
+        check(
+            4,
+            4,
+            &[
+                level(16, 0, 0, 0),
+                level(84, 0, 0, 0),
+                level(408, 0, 0, 0),
+                level(1764, 0, 0, 0),
+                level(7000, 0, 576, 0),
+                level(23248, 1960, 0, 0),
+                level(72200, 0, 11072, 0),
+                level(193616, 29944, 0, 0),
+            ],
+        );
+    }
+
+    #[test]
+    fn perft_5x6() {
+        check(
+            5,
+            6,
+            &[
+                level(30, 0, 0, 0),
+                level(178, 0, 0, 0),
+                level(976, 0, 0, 0),
+                level(4954, 0, 0, 0),
+                level(24356, 0, 1596, 0),
+                level(107854, 6300, 0, 0),
+                level(462684, 0, 43524, 0),
+                level(1847308, 163192, 0, 0),
+            ],
+        );
+    }
+}
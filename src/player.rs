@@ -0,0 +1,199 @@
+use std::io::Write;
+
+use rand::{prelude::StdRng, Rng, SeedableRng};
+
+use crate::{
+    expectimax::Expectimax,
+    genetic_builder::Bot,
+    grid::{CaseValue, Grid, GridProvider, MoveOutcome},
+    heuristic::HeuristicParams,
+    mcts::Mcts,
+    search::{Evaluator, Searcher},
+};
+
+// Anything that can pick a play for `color`, which is always the side to move. The returned play
+// must be legal.
+pub trait Player<T: GridProvider + Clone> {
+    fn choose_move(&mut self, grid: &Grid<T>, color: CaseValue) -> usize;
+}
+
+impl<T: GridProvider + Clone> Player<T> for Bot {
+    fn choose_move(&mut self, grid: &Grid<T>, color: CaseValue) -> usize {
+        self.best_play(grid, color)
+    }
+}
+
+impl<T: GridProvider + Clone + Sync, E: Evaluator + Sync> Player<T> for Searcher<E> {
+    fn choose_move(&mut self, grid: &Grid<T>, _color: CaseValue) -> usize {
+        self.best_play(grid)
+    }
+}
+
+impl<T: GridProvider + Clone, E: Evaluator> Player<T> for Expectimax<E> {
+    fn choose_move(&mut self, grid: &Grid<T>, _color: CaseValue) -> usize {
+        self.best_play(grid)
+    }
+}
+
+impl<T: GridProvider + Clone + Sync> Player<T> for Mcts {
+    fn choose_move(&mut self, grid: &Grid<T>, _color: CaseValue) -> usize {
+        self.best_play(grid)
+    }
+}
+
+pub struct RandomPlayer {
+    rng: StdRng,
+}
+
+impl RandomPlayer {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl<T: GridProvider + Clone> Player<T> for RandomPlayer {
+    fn choose_move(&mut self, grid: &Grid<T>, _color: CaseValue) -> usize {
+        let count = grid.legal_move_count();
+        grid.nth_legal_move(self.rng.gen_range(0..count)).unwrap()
+    }
+}
+
+// Plays `where_to_play`
+pub struct HeuristicPlayer {
+    params: HeuristicParams,
+}
+
+impl HeuristicPlayer {
+    pub fn new(params: HeuristicParams) -> Self {
+        Self { params }
+    }
+}
+
+impl<T: GridProvider + Clone> Player<T> for HeuristicPlayer {
+    fn choose_move(&mut self, grid: &Grid<T>, _color: CaseValue) -> usize {
+        grid.where_to_play(&self.params)
+    }
+}
+
+// Asks for `X Y` on stdin until the play is legal
+pub struct HumanPlayer;
+
+impl<T: GridProvider + Clone> Player<T> for HumanPlayer {
+    fn choose_move(&mut self, grid: &Grid<T>, _color: CaseValue) -> usize {
+        loop {
+            print!("Où jouer (X Y) : ");
+            std::io::stdout().flush().unwrap();
+            let mut input = String::new();
+            std::io::stdin().read_line(&mut input).unwrap();
+            let coordinates = input
+                .split_whitespace()
+                .map(|x| x.parse::<usize>())
+                .collect::<Result<Vec<_>, _>>();
+            let (x, y) = match coordinates.as_deref() {
+                Ok([x, y]) if *x < grid.width() && *y < grid.height() => (*x, *y),
+                _ => {
+                    println!("Expected two coordinates inside the grid");
+                    continue;
+                }
+            };
+            let index = grid.x_y_to_index(x, y);
+            match grid.clone().play(index) {
+                Ok(_) => return index,
+                Err(e) => println!("Invalid position: {}", e),
+            }
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug, serde::Deserialize, serde::Serialize)]
+pub struct CompareResult {
+    pub win: usize,
+    pub loose: usize,
+    pub none: usize,
+}
+
+impl CompareResult {
+    pub fn score(&self) -> isize {
+        self.win as isize - self.loose as isize
+    }
+}
+
+// Plays until the end of the game and returns the final position
+pub fn play_game<T, R, B>(mut grid: Grid<T>, red: &mut R, blue: &mut B) -> Grid<T>
+where
+    T: GridProvider + Clone,
+    R: Player<T> + ?Sized,
+    B: Player<T> + ?Sized,
+{
+    while !grid.is_over() {
+        let index = if grid.is_red_turn() {
+            red.choose_move(&grid, CaseValue::Red)
+        } else {
+            blue.choose_move(&grid, CaseValue::Blue)
+        };
+        grid.play(index).unwrap();
+    }
+    grid
+}
+
+// Result of `a` against `b` over `games` games from `start`, `a` plays red in the even games. Each
+// opening is played twice so both players get both sides of it.
+pub fn play_match<T, A, B>(
+    start: &Grid<T>,
+    a: &mut A,
+    b: &mut B,
+    games: usize,
+    openings: &[Vec<usize>],
+) -> CompareResult
+where
+    T: GridProvider + Clone,
+    A: Player<T> + ?Sized,
+    B: Player<T> + ?Sized,
+{
+    let mut result = CompareResult::default();
+    for game in 0..games {
+        let mut grid = start.clone();
+        if !openings.is_empty() {
+            for index in &openings[game / 2 % openings.len()] {
+                grid.play(*index).unwrap();
+            }
+        }
+        let a_is_red = game % 2 == 0;
+        let grid = if a_is_red {
+            play_game(grid, a, b)
+        } else {
+            play_game(grid, b, a)
+        };
+        match grid.status() {
+            MoveOutcome::RedWin(_) if a_is_red => result.win += 1,
+            MoveOutcome::BlueWin(_) if !a_is_red => result.win += 1,
+            MoveOutcome::RedWin(_) | MoveOutcome::BlueWin(_) => result.loose += 1,
+            _ => result.none += 1,
+        }
+    }
+    result
+}
+
+// Random plays from `start` that don't end the game
+pub fn random_openings<T: GridProvider + Clone>(
+    start: &Grid<T>,
+    count: usize,
+    plies: usize,
+    rng: &mut impl Rng,
+) -> Vec<Vec<usize>> {
+    (0..count)
+        .map(|_| {
+            let mut grid = start.clone();
+            for _ in 0..plies {
+                grid.random_play(rng).unwrap();
+                if grid.is_over() {
+                    grid.undo();
+                    break;
+                }
+            }
+            grid.moves().collect()
+        })
+        .collect()
+}
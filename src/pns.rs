@@ -0,0 +1,205 @@
+use crate::{
+    grid::{Grid, GridProvider},
+    solver::{outcome_value, GameValue},
+};
+
+const INFINITY: u32 = u32::MAX;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Proof {
+    // The side to move can force a win
+    Win,
+    // It can't, the best it can get is a draw or a loss
+    NoWin,
+    // The search ran out of nodes
+    Unknown,
+}
+
+// Plays of a forced win: one play for the winner, every reply for the loser
+#[derive(Clone, Debug)]
+pub struct ProofTree {
+    pub plays: Vec<(usize, ProofTree)>,
+}
+
+impl ProofTree {
+    // Plays until the end of the longest line, so the win takes at most this many plays
+    pub fn depth(&self) -> u32 {
+        self.plays
+            .iter()
+            .map(|x| x.1.depth() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Replies that make the game last the longest
+    pub fn main_line(&self) -> Vec<usize> {
+        match self.plays.iter().max_by_key(|x| x.1.depth()) {
+            Some((index, tree)) => {
+                let mut line = vec![*index];
+                line.extend(tree.main_line());
+                line
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PnsResult {
+    pub proof: Proof,
+    // Only for a proven win
+    pub tree: Option<ProofTree>,
+    pub nodes: usize,
+}
+
+struct Node {
+    index: usize,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    proof: u32,
+    disproof: u32,
+    // The side we try to prove a win for is to move
+    is_or: bool,
+}
+
+// Proof-number search on a tree without transpositions, it stops after `max_nodes` nodes
+#[derive(Clone, Copy, Debug)]
+pub struct ProofSearch {
+    pub max_nodes: usize,
+}
+
+impl ProofSearch {
+    pub fn new(max_nodes: usize) -> Self {
+        Self { max_nodes }
+    }
+
+    pub fn prove<T: GridProvider + Clone>(&self, grid: &Grid<T>) -> PnsResult {
+        let mut grid = grid.clone();
+        let is_red = grid.is_red_turn();
+        let (proof, disproof) = numbers(&grid, is_red);
+        let mut nodes = vec![Node {
+            index: 0,
+            parent: None,
+            children: Vec::new(),
+            proof,
+            disproof,
+            is_or: true,
+        }];
+        while nodes[0].proof != 0 && nodes[0].disproof != 0 && nodes.len() < self.max_nodes {
+            // Most proving node
+            let mut node = 0;
+            let mut plays = 0;
+            while !nodes[node].children.is_empty() {
+                let children = &nodes[node].children;
+                node = if nodes[node].is_or {
+                    *children.iter().min_by_key(|x| nodes[**x].proof).unwrap()
+                } else {
+                    *children.iter().min_by_key(|x| nodes[**x].disproof).unwrap()
+                };
+                grid.play(nodes[node].index).unwrap();
+                plays += 1;
+            }
+            // Expansion
+            let mut next = grid.next_legal_move(0);
+            while let Some(index) = next {
+                grid.play(index).unwrap();
+                let (proof, disproof) = numbers(&grid, is_red);
+                grid.undo();
+                nodes.push(Node {
+                    index,
+                    parent: Some(node),
+                    children: Vec::new(),
+                    proof,
+                    disproof,
+                    is_or: !nodes[node].is_or,
+                });
+                let child = nodes.len() - 1;
+                nodes[node].children.push(child);
+                next = grid.next_legal_move(index + 1);
+            }
+            // Update of the ancestors
+            let mut current = Some(node);
+            while let Some(x) = current {
+                let children = nodes[x].children.iter().map(|x| &nodes[*x]);
+                let (proof, disproof) = if nodes[x].is_or {
+                    children.fold((INFINITY, 0u32), |acc, x| {
+                        (acc.0.min(x.proof), acc.1.saturating_add(x.disproof))
+                    })
+                } else {
+                    children.fold((0u32, INFINITY), |acc, x| {
+                        (acc.0.saturating_add(x.proof), acc.1.min(x.disproof))
+                    })
+                };
+                nodes[x].proof = proof;
+                nodes[x].disproof = disproof;
+                current = nodes[x].parent;
+            }
+            for _ in 0..plays {
+                grid.undo();
+            }
+        }
+        let proof = if nodes[0].proof == 0 {
+            Proof::Win
+        } else if nodes[0].disproof == 0 {
+            Proof::NoWin
+        } else {
+            Proof::Unknown
+        };
+        PnsResult {
+            proof,
+            tree: if proof == Proof::Win {
+                Some(proof_tree(&nodes, 0))
+            } else {
+                None
+            },
+            nodes: nodes.len(),
+        }
+    }
+
+    // Plays after which the opponent can force a win, with at most how many plays it takes
+    pub fn losing_moves<T: GridProvider + Clone>(&self, grid: &Grid<T>) -> Vec<(usize, u32)> {
+        let mut grid = grid.clone();
+        let mut losing = Vec::new();
+        let mut next = grid.next_legal_move(0);
+        while let Some(index) = next {
+            grid.play(index).unwrap();
+            if grid.is_over() {
+                if outcome_value(grid.status(), grid.is_red_turn()) == Some(GameValue::Win) {
+                    losing.push((index, 1));
+                }
+            } else if let Some(tree) = self.prove(&grid).tree {
+                losing.push((index, tree.depth() + 1));
+            }
+            grid.undo();
+            next = grid.next_legal_move(index + 1);
+        }
+        losing
+    }
+}
+
+// Proof and disproof numbers of a new node, a win for `is_red` is proven
+fn numbers<T: GridProvider + Clone>(grid: &Grid<T>, is_red: bool) -> (u32, u32) {
+    match outcome_value(grid.status(), is_red) {
+        Some(GameValue::Win) => (0, INFINITY),
+        Some(_) => (INFINITY, 0),
+        None => (1, 1),
+    }
+}
+
+// Shortest proof below a proven node
+fn proof_tree(nodes: &[Node], node: usize) -> ProofTree {
+    let proven = nodes[node]
+        .children
+        .iter()
+        .filter(|x| nodes[**x].proof == 0)
+        .map(|x| (nodes[*x].index, proof_tree(nodes, *x)));
+    if nodes[node].is_or {
+        ProofTree {
+            plays: proven.min_by_key(|x| x.1.depth()).into_iter().collect(),
+        }
+    } else {
+        ProofTree {
+            plays: proven.collect(),
+        }
+    }
+}
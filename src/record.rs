@@ -0,0 +1,97 @@
+use std::fmt::Display;
+
+use crate::{
+    grid::{CaseValue, Grid, GridProvider, MoveOutcome},
+    rules::Rules,
+};
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct GameRecord {
+    pub width: usize,
+    pub height: usize,
+    pub rules: Rules,
+    pub walls: Vec<usize>,
+    pub moves: Vec<usize>,
+    pub result: MoveOutcome,
+    pub red: String,
+    pub blue: String,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    SizeMismatch,
+    // The play at this index of `moves` didn't give the same result as during the game
+    Diverged(usize),
+}
+
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SizeMismatch => write!(f, "the grid doesn't have the recorded size"),
+            Self::Diverged(ply) => write!(f, "the game diverged at play {}", ply + 1),
+        }
+    }
+}
+
+impl GameRecord {
+    pub fn from_grid<T: GridProvider + Clone>(grid: &Grid<T>, red: &str, blue: &str) -> Self {
+        Self {
+            width: grid.width(),
+            height: grid.height(),
+            rules: *grid.rules(),
+            walls: grid
+                .cases
+                .iter()
+                .enumerate()
+                .filter(|(_, value)| matches!(value, CaseValue::Black))
+                .map(|(a, _)| a)
+                .collect(),
+            moves: grid.moves().collect(),
+            result: grid.status().clone(),
+            red: red.to_owned(),
+            blue: blue.to_owned(),
+        }
+    }
+
+    pub fn save(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn load(s: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(s)
+    }
+
+    pub fn auto_save(&self) {
+        std::fs::create_dir_all("games").unwrap();
+        let n = format!("games/{}.json", std::fs::read_dir("games").unwrap().count());
+        std::fs::write(&n, self.save()).unwrap();
+        println!("Game saved in `{}`", n);
+    }
+
+    // Position before the first play
+    pub fn start<T: GridProvider + Clone>(&self, provider: T) -> Result<Grid<T>, ReplayError> {
+        if provider.width() != self.width || provider.height() != self.height {
+            return Err(ReplayError::SizeMismatch);
+        }
+        let mut grid = Grid::new(provider, self.rules);
+        for wall in &self.walls {
+            grid.set(*wall, CaseValue::Black);
+        }
+        Ok(grid)
+    }
+
+    pub fn replay<T: GridProvider + Clone>(&self, provider: T) -> Result<Grid<T>, ReplayError> {
+        let mut grid = self.start(provider)?;
+        for (ply, index) in self.moves.iter().enumerate() {
+            let expected = if ply + 1 == self.moves.len() {
+                self.result.clone()
+            } else {
+                MoveOutcome::Played
+            };
+            if grid.play(*index) != Ok(expected) {
+                return Err(ReplayError::Diverged(ply));
+            }
+        }
+        Ok(grid)
+    }
+}
@@ -0,0 +1,360 @@
+use std::fmt::Display;
+
+use rand::Rng;
+
+use crate::{
+    grid::{CaseValue, Direction, Grid, GridProvider, MoveOutcome, PlayError},
+    rules::{Neighbourhood, Rules},
+};
+
+// Deliberately naive rules engine: cells are plain coordinates, the playable cases are computed
+// from scratch on every call and nothing is cached. It is only there to check `Grid` against.
+#[derive(Clone)]
+pub struct ReferenceGame {
+    width: usize,
+    height: usize,
+    rules: Rules,
+    cells: Vec<CaseValue>,
+    is_red_turn: bool,
+    last_play: Option<usize>,
+    status: MoveOutcome,
+}
+
+#[derive(Debug)]
+pub struct Divergence {
+    // Position before the play that diverged, in the notation format
+    pub position: String,
+    pub play: Option<usize>,
+    pub reason: String,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.play {
+            Some(index) => write!(f, "{} playing {}: {}", self.position, index, self.reason),
+            None => write!(f, "{}: {}", self.position, self.reason),
+        }
+    }
+}
+
+fn offset(dir: Direction) -> (isize, isize) {
+    match dir {
+        Direction::North => (0, -1),
+        Direction::South => (0, 1),
+        Direction::East => (1, 0),
+        Direction::West => (-1, 0),
+        Direction::NorthEast => (1, -1),
+        Direction::NorthWest => (-1, -1),
+        Direction::SouthEast => (1, 1),
+        Direction::SouthWest => (-1, 1),
+    }
+}
+
+impl ReferenceGame {
+    pub fn new(width: usize, height: usize, rules: Rules) -> Self {
+        Self {
+            width,
+            height,
+            rules,
+            cells: vec![CaseValue::White; width * height],
+            is_red_turn: true,
+            last_play: None,
+            status: MoveOutcome::Played,
+        }
+    }
+
+    pub fn set_wall(&mut self, index: usize) {
+        self.cells[index] = CaseValue::Black;
+        if !(0..self.cells.len()).any(|x| self.is_empty(x)) {
+            self.status = MoveOutcome::NobodyWin;
+        }
+    }
+
+    fn is_empty(&self, index: usize) -> bool {
+        matches!(self.cells[index], CaseValue::Yellow | CaseValue::White)
+    }
+
+    // Case at `(x + dx, y + dy)` from `index` if it is in the grid
+    fn step(&self, index: usize, dx: isize, dy: isize) -> Option<usize> {
+        let x = (index % self.width) as isize + dx;
+        let y = (index / self.width) as isize + dy;
+        if x < 0 || y < 0 || x >= self.width as isize || y >= self.height as isize {
+            None
+        } else {
+            Some(y as usize * self.width + x as usize)
+        }
+    }
+
+    pub fn legal_moves(&self) -> Vec<usize> {
+        if self.status.is_over() {
+            return Vec::new();
+        }
+        let empty = (0..self.cells.len())
+            .filter(|x| self.is_empty(*x))
+            .collect::<Vec<_>>();
+        let around = match self.last_play {
+            Some(last) => empty
+                .iter()
+                .copied()
+                .filter(|x| {
+                    self.rules.neighbourhood.directions().iter().any(|dir| {
+                        let (dx, dy) = offset(*dir);
+                        self.step(last, dx, dy) == Some(*x)
+                    })
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        if around.is_empty() {
+            empty
+        } else {
+            around
+        }
+    }
+
+    pub fn play(&mut self, index: usize) -> Result<MoveOutcome, PlayError> {
+        if self.status.is_over() {
+            return Err(PlayError::GameOver);
+        }
+        match self.cells.get(index) {
+            None => return Err(PlayError::OutOfBounds),
+            Some(CaseValue::Red) | Some(CaseValue::Blue) => return Err(PlayError::Occupied),
+            Some(CaseValue::Black) => return Err(PlayError::Wall),
+            _ => (),
+        }
+        if !self.legal_moves().contains(&index) {
+            return Err(PlayError::NotAdjacent);
+        }
+        let color = if self.is_red_turn {
+            CaseValue::Red
+        } else {
+            CaseValue::Blue
+        };
+        self.cells[index] = color;
+        self.is_red_turn = !self.is_red_turn;
+        self.last_play = Some(index);
+        self.status = MoveOutcome::Played;
+        for (dx, dy) in [(1, 0), (0, 1), (1, 1), (-1, 1)] {
+            if self.run(index, dx, dy, color) + self.run(index, -dx, -dy, color) + 1
+                >= self.rules.line_length
+            {
+                self.status = if (color == CaseValue::Red) != self.rules.misere {
+                    MoveOutcome::RedWin(self.line(index, dx, dy, color))
+                } else {
+                    MoveOutcome::BlueWin(self.line(index, dx, dy, color))
+                };
+                return Ok(self.status.clone());
+            }
+        }
+        if !(0..self.cells.len()).any(|x| self.is_empty(x)) {
+            self.status = MoveOutcome::NobodyWin;
+        }
+        Ok(self.status.clone())
+    }
+
+    // Number of cases of `color` after `index` going by `(dx, dy)`
+    fn run(&self, index: usize, dx: isize, dy: isize, color: CaseValue) -> usize {
+        let mut count = 0;
+        let mut current = index;
+        while let Some(next) = self.step(current, dx, dy) {
+            if self.cells[next] != color {
+                break;
+            }
+            count += 1;
+            current = next;
+        }
+        count
+    }
+
+    fn line(&self, index: usize, dx: isize, dy: isize, color: CaseValue) -> crate::grid::Line {
+        let mut start = index;
+        for _ in 0..self.run(index, -dx, -dy, color) {
+            start = self.step(start, -dx, -dy).unwrap();
+        }
+        let mut cells = vec![start];
+        for _ in 0..self.run(start, dx, dy, color) {
+            cells.push(self.step(*cells.last().unwrap(), dx, dy).unwrap());
+        }
+        let direction = match (dx, dy) {
+            (1, 0) => Direction::East,
+            (0, 1) => Direction::South,
+            (1, 1) => Direction::SouthEast,
+            _ => Direction::SouthWest,
+        };
+        crate::grid::Line { cells, direction }
+    }
+
+    // Same format as `Grid::to_notation`, while the game goes on
+    pub fn to_notation(&self) -> String {
+        let legal = self.legal_moves();
+        let rows = (0..self.height)
+            .map(|y| {
+                (0..self.width)
+                    .map(|x| {
+                        let index = y * self.width + x;
+                        if self.is_empty(index) && legal.contains(&index) {
+                            '-'
+                        } else if self.is_empty(index) {
+                            '.'
+                        } else {
+                            self.cells[index].to_notation()
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        format!(
+            "{}x{}:{}:{}:{}",
+            self.width,
+            self.height,
+            rows,
+            if self.is_red_turn { 'r' } else { 'b' },
+            self.last_play
+                .map(|x| x.to_string())
+                .unwrap_or_else(|| "-".to_owned())
+        )
+    }
+}
+
+// The cases of `line` are consecutive, contain `index` and can't be extended
+fn is_line<T: GridProvider + Clone>(grid: &Grid<T>, line: &crate::grid::Line, index: usize) -> bool {
+    let color = grid.get(index);
+    let extends = |from: usize, dir: Direction| {
+        matches!(grid.follow_and_get(from, dir), Some((_, x)) if Some(x) == color)
+    };
+    line.cells.len() >= grid.rules().line_length
+        && line.cells.contains(&index)
+        && line.cells.iter().all(|x| grid.get(*x) == color)
+        && line
+            .cells
+            .windows(2)
+            .all(|x| grid.follow_and_get(x[0], line.direction).map(|x| x.0) == Some(x[1]))
+        && !extends(line.cells[0], line.direction.mirror())
+        && !extends(*line.cells.last().unwrap(), line.direction)
+}
+
+fn random_rules(rng: &mut impl Rng) -> Rules {
+    Rules::new(
+        rng.gen_range(2..=4),
+        rng.gen(),
+        match rng.gen_range(0..3) {
+            0 => Neighbourhood::Moore,
+            1 => Neighbourhood::VonNeumann,
+            _ => Neighbourhood::Diagonal,
+        },
+    )
+}
+
+// Plays random games on random sizes, rules and walls through both `Grid<T>` and the reference
+// engine, and stops at the first position where they disagree
+pub fn differential<T: GridProvider + Clone>(
+    games: usize,
+    rng: &mut impl Rng,
+) -> Result<(), Divergence> {
+    for _ in 0..games {
+        let width = rng.gen_range(1..=7);
+        let height = rng.gen_range(1..=7);
+        let rules = random_rules(rng);
+        let mut grid = Grid::new(T::with_size(width, height).unwrap(), rules);
+        let mut reference = ReferenceGame::new(width, height, rules);
+        for _ in 0..rng.gen_range(0..=(width * height / 4)) {
+            let wall = rng.gen_range(0..(width * height));
+            grid.set(wall, CaseValue::Black);
+            reference.set_wall(wall);
+        }
+        let start = grid.to_notation();
+        let start_hash = grid.hash();
+        loop {
+            let position = grid.to_notation();
+            let diverged = |play, reason: String| Divergence {
+                position: position.clone(),
+                play,
+                reason,
+            };
+            if position != reference.to_notation() {
+                return Err(diverged(
+                    None,
+                    format!("the reference engine has {}", reference.to_notation()),
+                ));
+            }
+            match Grid::<T>::parse_notation(&position, rules) {
+                Ok(parsed) if parsed.hash() == grid.hash() => (),
+                _ => return Err(diverged(None, "the hash was not updated correctly".to_owned())),
+            }
+            let legal = reference.legal_moves();
+            if grid.legal_moves().collect::<Vec<_>>() != legal {
+                return Err(diverged(
+                    None,
+                    format!("the reference engine can play {:?}", legal),
+                ));
+            }
+            // The walls took every case
+            if legal.is_empty() {
+                if !grid.is_over() {
+                    return Err(diverged(None, "a full grid is not a draw".to_owned()));
+                }
+                break;
+            }
+            // Sometimes try any case to compare the errors too
+            let index = if rng.gen_ratio(1, 8) {
+                rng.gen_range(0..(width * height + 2))
+            } else {
+                legal[rng.gen_range(0..legal.len())]
+            };
+            let expected = reference.play(index);
+            let result = grid.play(index);
+            // A play can complete lines on several axes, so only the winner has to match
+            let same = match (&result, &expected) {
+                (Ok(MoveOutcome::RedWin(line)), Ok(MoveOutcome::RedWin(_)))
+                | (Ok(MoveOutcome::BlueWin(line)), Ok(MoveOutcome::BlueWin(_))) => {
+                    is_line(&grid, line, index)
+                }
+                (a, b) => a == b,
+            };
+            if !same {
+                return Err(diverged(
+                    Some(index),
+                    format!("got {:?} instead of {:?}", result, expected),
+                ));
+            }
+            if grid.is_over() {
+                let index = rng.gen_range(0..(width * height));
+                if grid.play(index) != reference.play(index) {
+                    return Err(diverged(
+                        Some(index),
+                        "a play was accepted after the end".to_owned(),
+                    ));
+                }
+                break;
+            }
+        }
+        while grid.undo().is_some() {}
+        if grid.to_notation() != start || grid.hash() != start_hash {
+            return Err(Divergence {
+                position: grid.to_notation(),
+                play: None,
+                reason: format!("undoing every play didn't go back to {}", start),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bitboard::BitboardProvider, grid::VecProvider};
+    use rand::{prelude::StdRng, SeedableRng};
+
+    #[test]
+    fn providers_follow_the_reference() {
+        let mut rng = StdRng::seed_from_u64(0);
+        if let Err(e) = differential::<VecProvider>(3000, &mut rng) {
+            panic!("VecProvider: {}", e);
+        }
+        if let Err(e) = differential::<BitboardProvider>(3000, &mut rng) {
+            panic!("BitboardProvider: {}", e);
+        }
+    }
+}
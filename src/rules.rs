@@ -0,0 +1,64 @@
+use crate::grid::Direction;
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Rules {
+    // Number of aligned cells of the same color that ends the game
+    pub line_length: usize,
+    // In misère the player completing a line loses, otherwise they win
+    pub misere: bool,
+    // Cells around the last play that become playable for the next move
+    pub neighbourhood: Neighbourhood,
+}
+
+impl Rules {
+    pub fn new(line_length: usize, misere: bool, neighbourhood: Neighbourhood) -> Self {
+        assert!(line_length > 1);
+        Self {
+            line_length,
+            misere,
+            neighbourhood,
+        }
+    }
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self::new(3, true, Neighbourhood::Moore)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum Neighbourhood {
+    Moore,
+    VonNeumann,
+    Diagonal,
+}
+
+impl Neighbourhood {
+    pub fn directions(&self) -> &'static [Direction] {
+        match self {
+            Self::Moore => &[
+                Direction::North,
+                Direction::East,
+                Direction::West,
+                Direction::South,
+                Direction::NorthEast,
+                Direction::NorthWest,
+                Direction::SouthEast,
+                Direction::SouthWest,
+            ],
+            Self::VonNeumann => &[
+                Direction::North,
+                Direction::East,
+                Direction::West,
+                Direction::South,
+            ],
+            Self::Diagonal => &[
+                Direction::NorthEast,
+                Direction::NorthWest,
+                Direction::SouthEast,
+                Direction::SouthWest,
+            ],
+        }
+    }
+}
@@ -0,0 +1,529 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    genetic_builder::Bot,
+    grid::{CaseValue, Grid, GridProvider},
+    heuristic::HeuristicParams,
+    solver::{outcome_value, GameValue},
+    tablebase::Tablebase,
+    transposition::SharedTable,
+};
+
+// Scores of finished games, shortened by the number of plays needed to get there so the search
+// prefers quick wins and slow losses
+const WIN_SCORE: f32 = 1000.;
+
+pub trait Evaluator {
+    // Score of the position for the side to move, between -1 and 1
+    fn evaluate<T: GridProvider + Clone>(&self, grid: &Grid<T>) -> f32;
+}
+
+// Share of the plays that don't lose at once for the side to move, then the empty cases good for
+// it minus the ones good for the opponent, then the `evaluate_play` sums of the cases that don't
+// complete a line. In misère a case is good when a stone there doesn't complete a line, otherwise
+// when it does.
+pub struct Heuristic {
+    params: HeuristicParams,
+}
+
+impl Heuristic {
+    pub fn new(params: HeuristicParams) -> Self {
+        Self { params }
+    }
+}
+
+impl Evaluator for Heuristic {
+    fn evaluate<T: GridProvider + Clone>(&self, grid: &Grid<T>) -> f32 {
+        let params = &self.params;
+        let misere = grid.rules().misere;
+        let color = turn_color(grid);
+        let other = match color {
+            CaseValue::Red => CaseValue::Blue,
+            _ => CaseValue::Red,
+        };
+        let (mut safe, mut total) = (0, 0);
+        for index in grid.legal_moves() {
+            let completes = grid.completes_line(index, color);
+            // Without misère completing a line wins
+            if completes && !misere {
+                return 1.;
+            }
+            safe += !completes as usize;
+            total += 1;
+        }
+        if total == 0 {
+            return 0.;
+        }
+        let (mut balance, mut sums, mut empty) = (0., 0., 0.);
+        for index in 0..grid.width() * grid.height() {
+            if !matches!(grid.get(index), Some(CaseValue::Yellow | CaseValue::White)) {
+                continue;
+            }
+            empty += 1.;
+            for (color, sign) in [(color, 1.), (other, -1.)] {
+                let completes = grid.completes_line(index, color);
+                if completes != misere {
+                    balance += sign;
+                }
+                if !completes {
+                    sums += sign * grid.evaluate_play(index, color, params);
+                }
+            }
+        }
+        params.safe_share * (2. * safe as f32 / total as f32 - 1.)
+            + params.safe_balance * balance / empty
+            + params.safe_sums * (sums / empty).tanh()
+    }
+}
+
+// The network scores a position for the player who just played, lower is better for them. Its
+// output is at least 0 and only its order matters, so it is squashed into the evaluator range. The
+// network only knows 5x6 grids.
+impl Evaluator for Bot {
+    fn evaluate<T: GridProvider + Clone>(&self, grid: &Grid<T>) -> f32 {
+        let last = if grid.is_red_turn() {
+            CaseValue::Blue
+        } else {
+            CaseValue::Red
+        };
+        let x = self.execute(grid, last);
+        1. - 2. / (1. + x)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SearchLimits {
+    pub depth: u8,
+    // The deepest finished iteration is kept when the time runs out
+    pub time: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub best_move: Option<usize>,
+    pub score: f32,
+    pub depth: u8,
+    // Principal variation, starting with `best_move`
+    pub pv: Vec<usize>,
+    pub nodes: u64,
+}
+
+#[derive(Clone, Copy)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    depth: u8,
+    score: f32,
+    bound: Bound,
+    best_move: Option<usize>,
+}
+
+impl Entry {
+    // The score bits, then the depth, the bound and the best play plus one
+    fn pack(&self) -> u64 {
+        self.score.to_bits() as u64
+            | (self.depth as u64) << 32
+            | (self.bound as u64) << 40
+            | (self.best_move.map_or(0, |x| x + 1) as u64) << 42
+    }
+
+    fn unpack(data: u64) -> Self {
+        Self {
+            score: f32::from_bits(data as u32),
+            depth: (data >> 32) as u8,
+            bound: match (data >> 40) & 3 {
+                0 => Bound::Exact,
+                1 => Bound::Lower,
+                _ => Bound::Upper,
+            },
+            best_move: ((data >> 42) as usize).checked_sub(1),
+        }
+    }
+}
+
+// Negamax with alpha-beta pruning and iterative deepening. With several threads the helpers search
+// the same position and share the transposition table (lazy SMP), only the main thread gives the
+// result.
+pub struct Searcher<E: Evaluator> {
+    evaluator: E,
+    pub limits: SearchLimits,
+    threads: usize,
+    tablebase: Option<Arc<Tablebase>>,
+    table: SharedTable,
+}
+
+impl<E: Evaluator + Sync> Searcher<E> {
+    pub fn new(evaluator: E, limits: SearchLimits) -> Self {
+        Self {
+            evaluator,
+            limits,
+            threads: 1,
+            tablebase: None,
+            table: SharedTable::new(18),
+        }
+    }
+
+    pub fn with_tablebase(self, tablebase: Arc<Tablebase>) -> Self {
+        Self {
+            tablebase: Some(tablebase),
+            ..self
+        }
+    }
+
+    // A single thread always gives the same result for a position
+    pub fn with_threads(self, threads: usize) -> Self {
+        Self {
+            threads: threads.max(1),
+            ..self
+        }
+    }
+
+    pub fn best_play<T: GridProvider + Clone + Sync>(&mut self, grid: &Grid<T>) -> usize {
+        self.search(grid).best_move.unwrap()
+    }
+
+    pub fn search<T: GridProvider + Clone + Sync>(&mut self, grid: &Grid<T>) -> SearchResult {
+        let stop = AtomicBool::new(false);
+        let deadline = self.limits.time.map(|x| Instant::now() + x);
+        let worker = |thread: usize| Worker {
+            evaluator: &self.evaluator,
+            tablebase: self.tablebase.as_deref(),
+            table: &self.table,
+            stop: &stop,
+            deadline,
+            nodes: 0,
+            thread,
+        };
+        if self.threads == 1 {
+            return worker(0).iterate(grid, self.limits.depth);
+        }
+        let results = (0..self.threads)
+            .into_par_iter()
+            .map(|thread| {
+                let result = worker(thread).iterate(grid, self.limits.depth);
+                if thread == 0 {
+                    stop.store(true, Ordering::Relaxed);
+                }
+                result
+            })
+            .collect::<Vec<_>>();
+        SearchResult {
+            nodes: results.iter().map(|x| x.nodes).sum(),
+            ..results.into_iter().next().unwrap()
+        }
+    }
+
+    // Score of every play for the side to move with its principal variation, each searched with a
+    // full window to the depth limit. The time limit and the threads are not used.
+    pub fn search_moves<T: GridProvider + Clone>(
+        &mut self,
+        grid: &Grid<T>,
+    ) -> Vec<(usize, f32, Vec<usize>)> {
+        let stop = AtomicBool::new(false);
+        let mut worker = Worker {
+            evaluator: &self.evaluator,
+            tablebase: self.tablebase.as_deref(),
+            table: &self.table,
+            stop: &stop,
+            deadline: None,
+            nodes: 0,
+            thread: 0,
+        };
+        let mut grid = grid.clone();
+        let moves = grid.legal_moves().collect::<Vec<_>>();
+        moves
+            .into_iter()
+            .map(|index| {
+                let mut pv = Vec::new();
+                grid.play(index).unwrap();
+                let depth = self.limits.depth.saturating_sub(1);
+                let score = -worker.negamax(
+                    &mut grid,
+                    depth,
+                    1,
+                    -f32::INFINITY,
+                    f32::INFINITY,
+                    &mut pv,
+                );
+                grid.undo();
+                pv.insert(0, index);
+                (index, score, pv)
+            })
+            .collect()
+    }
+}
+
+struct Worker<'a, E: Evaluator> {
+    evaluator: &'a E,
+    tablebase: Option<&'a Tablebase>,
+    table: &'a SharedTable,
+    stop: &'a AtomicBool,
+    deadline: Option<Instant>,
+    nodes: u64,
+    thread: usize,
+}
+
+impl<'a, E: Evaluator> Worker<'a, E> {
+    fn iterate<T: GridProvider + Clone>(&mut self, grid: &Grid<T>, max_depth: u8) -> SearchResult {
+        let mut grid = grid.clone();
+        let mut result = SearchResult {
+            best_move: grid.legal_moves().next().filter(|_| !grid.is_over()),
+            score: 0.,
+            depth: 0,
+            pv: Vec::new(),
+            nodes: 0,
+        };
+        if grid.is_over() {
+            return result;
+        }
+        // Helpers skip some depths so they don't all search the same tree at once
+        let first = 1 + (self.thread % 2) as u8;
+        for depth in first.min(max_depth)..=max_depth {
+            let mut pv = Vec::new();
+            let score = self.negamax(
+                &mut grid,
+                depth,
+                0,
+                -f32::INFINITY,
+                f32::INFINITY,
+                &mut pv,
+            );
+            if self.stopped() {
+                break;
+            }
+            result = SearchResult {
+                best_move: pv.first().copied(),
+                score,
+                depth,
+                pv,
+                nodes: self.nodes,
+            };
+            // Searching deeper won't change a forced result
+            if is_decisive(score) {
+                break;
+            }
+        }
+        result.nodes = self.nodes;
+        result
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    fn negamax<T: GridProvider + Clone>(
+        &mut self,
+        grid: &mut Grid<T>,
+        depth: u8,
+        ply: u32,
+        mut alpha: f32,
+        beta: f32,
+        pv: &mut Vec<usize>,
+    ) -> f32 {
+        pv.clear();
+        if let Some(value) = outcome_value(grid.status(), grid.is_red_turn()) {
+            return value_score(value, ply);
+        }
+        if ply > 0 {
+            if let Some((value, distance)) = self.tablebase.and_then(|x| x.lookup(grid)) {
+                return value_score(value, ply + distance);
+            }
+        }
+        if depth == 0 {
+            return self.evaluator.evaluate(grid);
+        }
+        self.nodes += 1;
+        if self.nodes % 1024 == 0 && self.deadline.map_or(false, |x| Instant::now() >= x) {
+            self.stop.store(true, Ordering::Relaxed);
+        }
+        if self.stopped() {
+            return 0.;
+        }
+
+        let alpha_start = alpha;
+        let mut table_move = None;
+        if let Some(entry) = self.table.get(grid.hash()).map(Entry::unpack) {
+            table_move = entry.best_move;
+            if ply > 0 && entry.depth >= depth {
+                let score = from_table(entry.score, ply);
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => (),
+                }
+            }
+        }
+
+        let mut best = -f32::INFINITY;
+        let mut best_move = None;
+        let mut child_pv = Vec::new();
+        for index in order_moves(grid, table_move) {
+            grid.play(index).unwrap();
+            let score = -self.negamax(grid, depth - 1, ply + 1, -beta, -alpha, &mut child_pv);
+            grid.undo();
+            if self.stopped() {
+                return 0.;
+            }
+            if score > best {
+                best = score;
+                best_move = Some(index);
+                if score > alpha {
+                    alpha = score;
+                    pv.clear();
+                    pv.push(index);
+                    pv.extend_from_slice(&child_pv);
+                }
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+
+        let bound = if best <= alpha_start {
+            Bound::Upper
+        } else if best >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        let entry = Entry {
+            depth,
+            score: to_table(best, ply),
+            bound,
+            best_move,
+        };
+        self.table.insert(grid.hash(), entry.pack());
+        best
+    }
+}
+
+fn turn_color<T: GridProvider + Clone>(grid: &Grid<T>) -> CaseValue {
+    if grid.is_red_turn() {
+        CaseValue::Red
+    } else {
+        CaseValue::Blue
+    }
+}
+
+// Best play of the previous iteration first, then the safest plays according to `evaluate_play`
+fn order_moves<T: GridProvider + Clone>(grid: &Grid<T>, first: Option<usize>) -> Vec<usize> {
+    let color = turn_color(grid);
+    let mut moves = grid
+        .legal_moves()
+        .map(|x| {
+            let key = if Some(x) == first {
+                f32::NEG_INFINITY
+            } else {
+                grid.evaluate_play(x, color, &HeuristicParams::DEFAULT)
+            };
+            (x, key)
+        })
+        .collect::<Vec<_>>();
+    moves.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+    moves.into_iter().map(|x| x.0).collect()
+}
+
+fn value_score(value: GameValue, ply: u32) -> f32 {
+    match value {
+        GameValue::Win => WIN_SCORE - ply as f32,
+        GameValue::Draw => 0.,
+        GameValue::Loss => ply as f32 - WIN_SCORE,
+    }
+}
+
+pub fn is_decisive(score: f32) -> bool {
+    score.abs() > WIN_SCORE / 2.
+}
+
+// Plays until the end of the game for a decisive score
+pub fn plays_to_end(score: f32) -> u32 {
+    (WIN_SCORE - score.abs()).round() as u32
+}
+
+// Finished game scores are stored relative to the position so they stay right when it is reached
+// at another ply
+fn to_table(score: f32, ply: u32) -> f32 {
+    if score > WIN_SCORE / 2. {
+        score + ply as f32
+    } else if score < -WIN_SCORE / 2. {
+        score - ply as f32
+    } else {
+        score
+    }
+}
+
+fn from_table(score: f32, ply: u32) -> f32 {
+    if score > WIN_SCORE / 2. {
+        score - ply as f32
+    } else if score < -WIN_SCORE / 2. {
+        score + ply as f32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bitboard::BitboardProvider, rules::Rules, solver::Solver};
+    use rand::{prelude::StdRng, SeedableRng};
+
+    // Deep enough to reach the end of every line, the search finds the solved value and distance
+    #[test]
+    fn full_depth_search_matches_the_solver() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut solver = Solver::new();
+        let limits = SearchLimits {
+            depth: 10,
+            time: None,
+        };
+        for _ in 0..30 {
+            let mut grid = Grid::new(BitboardProvider::new(3, 3), Rules::default());
+            for _ in 0..4 {
+                if !grid.is_over() {
+                    grid.random_play(&mut rng).unwrap();
+                }
+            }
+            if grid.is_over() {
+                continue;
+            }
+            let mut searcher = Searcher::new(Heuristic::new(HeuristicParams::DEFAULT), limits);
+            let result = searcher.search(&grid);
+            let solution = solver.solve(&mut grid.clone());
+            assert_eq!(
+                result.score,
+                value_score(solution.value, solution.distance),
+                "{}",
+                grid.to_notation()
+            );
+        }
+    }
+
+    #[test]
+    fn heuristic_avoids_completing_a_line() {
+        let grid: Grid<BitboardProvider> = "4x4:R.B./RB../..../....:r:5".parse().unwrap();
+        let limits = SearchLimits {
+            depth: 1,
+            time: None,
+        };
+        let mut searcher = Searcher::new(Heuristic::new(HeuristicParams::DEFAULT), limits);
+        let index = searcher.best_play(&grid);
+        let mut after = grid.clone();
+        after.play(index).unwrap();
+        assert!(!after.is_over(), "{} plays {}", grid.to_notation(), index);
+    }
+}
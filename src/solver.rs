@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+use crate::grid::{Grid, GridProvider, MoveOutcome};
+
+// Game theoretic value for the side to move
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameValue {
+    Win,
+    Draw,
+    Loss,
+}
+
+impl GameValue {
+    pub fn invert(&self) -> Self {
+        match self {
+            Self::Win => Self::Loss,
+            Self::Draw => Self::Draw,
+            Self::Loss => Self::Win,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Solution {
+    pub value: GameValue,
+    // Number of plays until the end of the game when both sides play optimally
+    pub distance: u32,
+    pub best_move: Option<usize>,
+}
+
+impl Solution {
+    // Wins are better when they are faster and losses when they are slower
+    pub fn is_better_than(&self, other: &Self) -> bool {
+        match (self.value, other.value) {
+            (GameValue::Win, GameValue::Win) => self.distance < other.distance,
+            (GameValue::Loss, GameValue::Loss) => self.distance > other.distance,
+            (GameValue::Win, _) | (GameValue::Draw, GameValue::Loss) => true,
+            _ => false,
+        }
+    }
+}
+
+// Value of a finished game for the side to move
+pub fn outcome_value(outcome: &MoveOutcome, is_red_turn: bool) -> Option<GameValue> {
+    match outcome {
+        MoveOutcome::Played => None,
+        MoveOutcome::NobodyWin => Some(GameValue::Draw),
+        MoveOutcome::RedWin(_) if is_red_turn => Some(GameValue::Win),
+        MoveOutcome::BlueWin(_) if !is_red_turn => Some(GameValue::Win),
+        _ => Some(GameValue::Loss),
+    }
+}
+
+#[derive(Default)]
+pub struct Solver {
+    memo: HashMap<u64, Solution>,
+    pub nodes: u64,
+}
+
+impl Solver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn solve<T: GridProvider + Clone>(&mut self, grid: &mut Grid<T>) -> Solution {
+        if let Some(value) = outcome_value(grid.status(), grid.is_red_turn()) {
+            return Solution {
+                value,
+                distance: 0,
+                best_move: None,
+            };
+        }
+        if let Some(solution) = self.memo.get(&grid.hash()) {
+            return *solution;
+        }
+        self.nodes += 1;
+        let mut best: Option<Solution> = None;
+        let mut next = grid.next_legal_move(0);
+        while let Some(index) = next {
+            grid.play(index).unwrap();
+            let child = self.solve(grid);
+            grid.undo();
+            let solution = Solution {
+                value: child.value.invert(),
+                distance: child.distance + 1,
+                best_move: Some(index),
+            };
+            if best.map_or(true, |x| solution.is_better_than(&x)) {
+                best = Some(solution);
+            }
+            // Nothing beats winning right now
+            if solution.value == GameValue::Win && solution.distance == 1 {
+                break;
+            }
+            next = grid.next_legal_move(index + 1);
+        }
+        // A position without plays is a draw, like a full grid
+        let best = best.unwrap_or(Solution {
+            value: GameValue::Draw,
+            distance: 0,
+            best_move: None,
+        });
+        self.memo.insert(grid.hash(), best);
+        best
+    }
+
+    // Value that the side to move gets by playing `index`, to grade other players' choices
+    pub fn grade<T: GridProvider + Clone>(&mut self, grid: &Grid<T>, index: usize) -> GameValue {
+        let mut grid = grid.clone();
+        let is_red_turn = grid.is_red_turn();
+        grid.play(index).unwrap();
+        match outcome_value(grid.status(), is_red_turn) {
+            Some(value) => value,
+            None => self.solve(&mut grid).value.invert(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{grid::VecProvider, rules::Rules};
+    use rand::{prelude::StdRng, SeedableRng};
+
+    // Plain negamax over every play, without the memo nor the early exit
+    fn naive(grid: &mut Grid<VecProvider>) -> (GameValue, u32) {
+        if let Some(value) = outcome_value(grid.status(), grid.is_red_turn()) {
+            return (value, 0);
+        }
+        let mut best: Option<Solution> = None;
+        for index in grid.legal_moves().collect::<Vec<_>>() {
+            grid.play(index).unwrap();
+            let (value, distance) = naive(grid);
+            grid.undo();
+            let solution = Solution {
+                value: value.invert(),
+                distance: distance + 1,
+                best_move: Some(index),
+            };
+            if best.map_or(true, |x| solution.is_better_than(&x)) {
+                best = Some(solution);
+            }
+        }
+        best.map_or((GameValue::Draw, 0), |x| (x.value, x.distance))
+    }
+
+    #[test]
+    fn solver_matches_negamax() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut solver = Solver::new();
+        for plays in 1..6 {
+            for _ in 0..20 {
+                let mut grid = Grid::new(VecProvider::new(3, 3), Rules::default());
+                for _ in 0..plays {
+                    if !grid.is_over() {
+                        grid.random_play(&mut rng).unwrap();
+                    }
+                }
+                let solution = solver.solve(&mut grid.clone());
+                assert_eq!(
+                    (solution.value, solution.distance),
+                    naive(&mut grid),
+                    "{}",
+                    grid.to_notation()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use crate::grid::{Grid, GridProvider};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Symmetry {
+    Identity,
+    FlipHorizontal,
+    FlipVertical,
+    Rotate180,
+    // The four last ones swap the width and the height so they only keep square grids
+    Transpose,
+    AntiTranspose,
+    Rotate90,
+    Rotate270,
+}
+
+impl Symmetry {
+    pub fn all(width: usize, height: usize) -> &'static [Symmetry] {
+        const ALL: [Symmetry; 8] = [
+            Symmetry::Identity,
+            Symmetry::FlipHorizontal,
+            Symmetry::FlipVertical,
+            Symmetry::Rotate180,
+            Symmetry::Transpose,
+            Symmetry::AntiTranspose,
+            Symmetry::Rotate90,
+            Symmetry::Rotate270,
+        ];
+        if width == height {
+            &ALL
+        } else {
+            &ALL[..4]
+        }
+    }
+
+    pub fn inverse(&self) -> Self {
+        match self {
+            Self::Rotate90 => Self::Rotate270,
+            Self::Rotate270 => Self::Rotate90,
+            x => *x,
+        }
+    }
+
+    // Maps a case index of a `width` x `height` grid to its index in the transformed grid
+    pub fn map(&self, index: usize, width: usize, height: usize) -> usize {
+        let (x, y) = (index % width, index / width);
+        let (x, y) = match self {
+            Self::Identity => (x, y),
+            Self::FlipHorizontal => (width - 1 - x, y),
+            Self::FlipVertical => (x, height - 1 - y),
+            Self::Rotate180 => (width - 1 - x, height - 1 - y),
+            Self::Transpose => (y, x),
+            Self::AntiTranspose => (height - 1 - y, width - 1 - x),
+            Self::Rotate90 => (height - 1 - y, x),
+            Self::Rotate270 => (y, width - 1 - x),
+        };
+        if self.swaps_sides() {
+            x + y * height
+        } else {
+            x + y * width
+        }
+    }
+
+    fn swaps_sides(&self) -> bool {
+        matches!(
+            self,
+            Self::Transpose | Self::AntiTranspose | Self::Rotate90 | Self::Rotate270
+        )
+    }
+}
+
+impl<T: GridProvider + Clone> Grid<T> {
+    pub fn transform(&self, symmetry: Symmetry) -> Self {
+        let (width, height) = (self.width(), self.height());
+        // Only square grids swap their sides, so the provider already holds this size
+        let mut cases = if symmetry.swaps_sides() {
+            T::with_size(height, width)
+        } else {
+            T::with_size(width, height)
+        }
+        .unwrap();
+        for (index, value) in self.cases.iter().enumerate() {
+            cases.set(symmetry.map(index, width, height), value);
+        }
+        Grid::from_position(
+            cases,
+            *self.rules(),
+            self.is_red_turn(),
+            self.last_play().map(|x| symmetry.map(x, width, height)),
+        )
+    }
+
+    pub fn symmetries(&self) -> Vec<Self> {
+        Symmetry::all(self.width(), self.height())
+            .iter()
+            .map(|x| self.transform(*x))
+            .collect()
+    }
+
+    // Same representative for every symmetric position, along with the symmetry that gives it
+    pub fn canonical_with_symmetry(&self) -> (Self, Symmetry) {
+        Symmetry::all(self.width(), self.height())
+            .iter()
+            .map(|x| (self.transform(*x), *x))
+            .min_by_key(|(grid, _)| grid.to_notation())
+            .unwrap()
+    }
+
+    pub fn canonical(&self) -> Self {
+        self.canonical_with_symmetry().0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bitboard::BitboardProvider, grid::CaseValue, rules::Rules};
+    use rand::{prelude::StdRng, SeedableRng};
+
+    // Every symmetry of the positions of random games has the same canonical hash and canonical
+    // position, and its inverse gets back to the position
+    fn check(width: usize, height: usize, rng: &mut StdRng) {
+        for _ in 0..50 {
+            let mut grid = Grid::new(BitboardProvider::new(width, height), Rules::default());
+            grid.set(0, CaseValue::Black);
+            while !grid.is_over() {
+                let canonical = grid.canonical().to_notation();
+                for symmetry in Symmetry::all(width, height) {
+                    let transformed = grid.transform(*symmetry);
+                    assert_eq!(transformed.canonical_hash(), grid.canonical_hash());
+                    assert_eq!(transformed.canonical().to_notation(), canonical);
+                    assert_eq!(
+                        transformed.transform(symmetry.inverse()).to_notation(),
+                        grid.to_notation()
+                    );
+                }
+                grid.random_play(rng).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn symmetric_positions_are_the_same() {
+        let mut rng = StdRng::seed_from_u64(0);
+        check(4, 4, &mut rng);
+        check(3, 5, &mut rng);
+    }
+}
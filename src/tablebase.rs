@@ -0,0 +1,403 @@
+use std::{convert::TryInto, fmt::Display};
+
+use crate::{
+    grid::{CaseValue, Grid, GridProvider},
+    rules::{Neighbourhood, Rules},
+    solver::{outcome_value, GameValue, Solution},
+    symmetry::Symmetry,
+};
+
+// The file starts with `MTB2`, the width, the height, the rules, the maximum number of empty cases
+// and the number of entries. Entries follow sorted by hash, each one is the canonical hash of the
+// position then a byte for the value of the side to move and a byte for the distance to the end of
+// the game. Symmetric positions have the same value, so they share one entry.
+const MAGIC: &[u8; 4] = b"MTB2";
+const HEADER_SIZE: usize = 14;
+const ENTRY_SIZE: usize = 10;
+
+#[derive(Debug)]
+pub enum TablebaseError {
+    Io(std::io::Error),
+    InvalidFormat,
+}
+
+impl Display for TablebaseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::InvalidFormat => write!(f, "not a tablebase file"),
+        }
+    }
+}
+
+pub struct Tablebase {
+    width: usize,
+    height: usize,
+    rules: Rules,
+    max_empty: usize,
+    entries: Vec<(u64, GameValue, u8)>,
+}
+
+impl Tablebase {
+    // Solves every position with at most `max_empty` empty cases that can follow `grid`, from the
+    // fewest empty cases up so the plays of a position lead to positions solved just before. Its
+    // empty cases are filled with as many stones of each colour as the plays give, without any
+    // completed line since that would have ended the game, and every stone of the side that just
+    // played can be the last play. Some of these positions can't be reached, which only costs
+    // entries.
+    pub fn generate<T: GridProvider + Clone>(grid: &Grid<T>, max_empty: usize) -> Self {
+        let empty = (0..grid.width() * grid.height())
+            .filter(|x| matches!(grid.get(*x), Some(CaseValue::Yellow | CaseValue::White)))
+            .collect::<Vec<_>>();
+        let rules = *grid.rules();
+        // The symmetries that keep the walls and stones of `grid` in place, the others lead to
+        // positions that aren't enumerated
+        let (width, height) = (grid.width(), grid.height());
+        let symmetries = Symmetry::all(width, height)
+            .iter()
+            .copied()
+            .filter(|symmetry| {
+                (0..width * height).all(|index| {
+                    let other = grid.get(symmetry.map(index, width, height));
+                    empty.contains(&index) || other == grid.get(index)
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut entries = Vec::new();
+        let mut previous = Vec::new();
+        for empty_left in 1..=max_empty.min(empty.len()) {
+            let mut current = Vec::new();
+            let mut add = |position: &mut Grid<T>| {
+                // Symmetric positions are solved once, from the one with the smallest hash
+                let hash = position.hash();
+                if !position.is_over()
+                    && symmetries
+                        .iter()
+                        .all(|x| position.symmetric_hash(*x) >= hash)
+                {
+                    let (value, distance) = Self::solve(position, &previous);
+                    current.push((position.canonical_hash(), value, distance as u8));
+                }
+            };
+            let plays = empty.len() - empty_left;
+            if plays == 0 {
+                add(&mut grid.clone());
+            } else {
+                // The side to move in `grid` makes the odd plays
+                let (first, second) = ((plays + 1) / 2, plays / 2);
+                let is_red_turn = grid.is_red_turn() == (plays % 2 == 0);
+                let (reds, blues) = if grid.is_red_turn() {
+                    (first, second)
+                } else {
+                    (second, first)
+                };
+                let last = if is_red_turn {
+                    CaseValue::Blue
+                } else {
+                    CaseValue::Red
+                };
+                let mut cases = grid.cases.clone();
+                Self::fill(
+                    &mut cases,
+                    &empty,
+                    [reds, blues, empty_left],
+                    rules.line_length,
+                    &mut |cases| {
+                        for index in empty.iter().filter(|x| cases.get(**x) == Some(last)) {
+                            let last_play = Some(*index);
+                            add(&mut Grid::from_position(
+                                cases.clone(),
+                                rules,
+                                is_red_turn,
+                                last_play,
+                            ));
+                        }
+                    },
+                );
+            }
+            // Once every case is unlocked the last play doesn't change the position
+            current.sort_unstable_by_key(|x| x.0);
+            current.dedup_by_key(|x| x.0);
+            entries.append(&mut previous);
+            previous = current;
+        }
+        entries.append(&mut previous);
+        entries.sort_unstable_by_key(|x| x.0);
+        entries.dedup_by_key(|x| x.0);
+        Self {
+            width: grid.width(),
+            height: grid.height(),
+            rules,
+            max_empty,
+            entries,
+        }
+    }
+
+    // Value and distance of a position whose plays all end the game or lead to `previous`
+    fn solve<T: GridProvider + Clone>(
+        grid: &mut Grid<T>,
+        previous: &[(u64, GameValue, u8)],
+    ) -> (GameValue, u32) {
+        let is_red_turn = grid.is_red_turn();
+        let mut best: Option<Solution> = None;
+        for index in grid.legal_moves().collect::<Vec<_>>() {
+            grid.play(index).unwrap();
+            let (value, distance) = match outcome_value(grid.status(), is_red_turn) {
+                Some(value) => (value, 1),
+                None => {
+                    let (value, distance) = find(previous, grid.canonical_hash())
+                        .expect("the play leads to a position with one empty case less");
+                    (value.invert(), distance + 1)
+                }
+            };
+            grid.undo();
+            let solution = Solution {
+                value,
+                distance,
+                best_move: Some(index),
+            };
+            if best.map_or(true, |x| solution.is_better_than(&x)) {
+                best = Some(solution);
+            }
+        }
+        let best = best.unwrap();
+        (best.value, best.distance)
+    }
+
+    // Every way to put `left` red stones, blue stones and empty cases on the `empty` cases without
+    // completing a line
+    fn fill<T: GridProvider + Clone>(
+        cases: &mut T,
+        empty: &[usize],
+        left: [usize; 3],
+        line_length: usize,
+        found: &mut impl FnMut(&T),
+    ) {
+        let (index, rest) = match empty.split_first() {
+            Some(x) => x,
+            None => return found(cases),
+        };
+        for (n, value) in [CaseValue::Red, CaseValue::Blue, CaseValue::White]
+            .iter()
+            .enumerate()
+        {
+            if left[n] == 0 {
+                continue;
+            }
+            cases.set(*index, *value);
+            if *value == CaseValue::White || cases.find_line(*index, *value, line_length).is_none()
+            {
+                let mut left = left;
+                left[n] -= 1;
+                Self::fill(cases, rest, left, line_length, found);
+            }
+        }
+        cases.set(*index, CaseValue::White);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn max_empty(&self) -> usize {
+        self.max_empty
+    }
+
+    fn get(&self, hash: u64) -> Option<(GameValue, u32)> {
+        find(&self.entries, hash)
+    }
+
+    fn matches<T: GridProvider + Clone>(&self, grid: &Grid<T>) -> bool {
+        grid.width() == self.width && grid.height() == self.height && *grid.rules() == self.rules
+    }
+
+    // Value and distance to the end for the side to move, without looking for the best play
+    pub fn lookup<T: GridProvider + Clone>(&self, grid: &Grid<T>) -> Option<(GameValue, u32)> {
+        if self.matches(grid) {
+            self.get(grid.canonical_hash())
+        } else {
+            None
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.entries.len() * ENTRY_SIZE);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&[
+            self.width as u8,
+            self.height as u8,
+            self.rules.line_length as u8,
+            self.rules.misere as u8,
+            match self.rules.neighbourhood {
+                Neighbourhood::Moore => 0,
+                Neighbourhood::VonNeumann => 1,
+                Neighbourhood::Diagonal => 2,
+            },
+            self.max_empty as u8,
+        ]);
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for (hash, value, distance) in &self.entries {
+            bytes.extend_from_slice(&hash.to_le_bytes());
+            bytes.push(match value {
+                GameValue::Loss => 0,
+                GameValue::Draw => 1,
+                GameValue::Win => 2,
+            });
+            bytes.push(*distance);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TablebaseError> {
+        if bytes.len() < HEADER_SIZE || &bytes[..4] != MAGIC {
+            return Err(TablebaseError::InvalidFormat);
+        }
+        let neighbourhood = match bytes[8] {
+            0 => Neighbourhood::Moore,
+            1 => Neighbourhood::VonNeumann,
+            2 => Neighbourhood::Diagonal,
+            _ => return Err(TablebaseError::InvalidFormat),
+        };
+        let count = u32::from_le_bytes(bytes[10..14].try_into().unwrap()) as usize;
+        if bytes[6] < 2 || bytes.len() != HEADER_SIZE + count * ENTRY_SIZE {
+            return Err(TablebaseError::InvalidFormat);
+        }
+        let entries = bytes[HEADER_SIZE..]
+            .chunks(ENTRY_SIZE)
+            .map(|x| {
+                let value = match x[8] {
+                    0 => GameValue::Loss,
+                    1 => GameValue::Draw,
+                    2 => GameValue::Win,
+                    _ => return Err(TablebaseError::InvalidFormat),
+                };
+                Ok((u64::from_le_bytes(x[..8].try_into().unwrap()), value, x[9]))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if entries.windows(2).any(|x| x[0].0 >= x[1].0) {
+            return Err(TablebaseError::InvalidFormat);
+        }
+        Ok(Self {
+            width: bytes[4] as usize,
+            height: bytes[5] as usize,
+            rules: Rules::new(bytes[6] as usize, bytes[7] != 0, neighbourhood),
+            max_empty: bytes[9] as usize,
+            entries,
+        })
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_bytes())
+    }
+
+    pub fn load(path: &str) -> Result<Self, TablebaseError> {
+        Self::from_bytes(&std::fs::read(path).map_err(TablebaseError::Io)?)
+    }
+}
+
+fn find(entries: &[(u64, GameValue, u8)], hash: u64) -> Option<(GameValue, u32)> {
+    let index = entries.binary_search_by_key(&hash, |x| x.0).ok()?;
+    let (_, value, distance) = entries[index];
+    Some((value, distance as u32))
+}
+
+impl<T: GridProvider + Clone> Grid<T> {
+    // Value and best play of the position if it is in the tablebase
+    pub fn probe(&self, tablebase: &Tablebase) -> Option<Solution> {
+        if !tablebase.matches(self) {
+            return None;
+        }
+        if let Some(value) = outcome_value(self.status(), self.is_red_turn()) {
+            return Some(Solution {
+                value,
+                distance: 0,
+                best_move: None,
+            });
+        }
+        let (value, distance) = tablebase.lookup(self)?;
+        let mut grid = self.clone();
+        let mut best: Option<Solution> = None;
+        for index in self.legal_moves() {
+            grid.play(index).unwrap();
+            let child = match outcome_value(grid.status(), self.is_red_turn()) {
+                Some(value) => Some((value, 1)),
+                None => tablebase
+                    .get(grid.canonical_hash())
+                    .map(|(value, distance)| (value.invert(), distance + 1)),
+            };
+            grid.undo();
+            if let Some((value, distance)) = child {
+                let solution = Solution {
+                    value,
+                    distance,
+                    best_move: Some(index),
+                };
+                if best.map_or(true, |x| solution.is_better_than(&x)) {
+                    best = Some(solution);
+                }
+            }
+        }
+        Some(Solution {
+            value,
+            distance,
+            best_move: best.and_then(|x| x.best_move),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bitboard::BitboardProvider, rules::Rules, solver::Solver};
+    use rand::{prelude::StdRng, SeedableRng};
+
+    // Every position of random games with few enough empty cases is probed to its solved value,
+    // and the best play keeps it
+    fn check(tablebase: &Tablebase, start: &Grid<BitboardProvider>, rng: &mut StdRng) {
+        let mut solver = Solver::new();
+        for _ in 0..100 {
+            let mut grid = start.clone();
+            while !grid.is_over() {
+                let empty = grid
+                    .cases
+                    .iter()
+                    .filter(|x| matches!(x, CaseValue::Yellow | CaseValue::White))
+                    .count();
+                if empty <= tablebase.max_empty() {
+                    let probe = grid.probe(tablebase).expect(&grid.to_notation());
+                    let solution = solver.solve(&mut grid.clone());
+                    assert_eq!(
+                        (probe.value, probe.distance),
+                        (solution.value, solution.distance)
+                    );
+                    let mut child = grid.clone();
+                    child.play(probe.best_move.unwrap()).unwrap();
+                    let after = solver.solve(&mut child);
+                    assert_eq!(after.value.invert(), solution.value);
+                    assert_eq!(after.distance + 1, solution.distance);
+                }
+                grid.random_play(rng).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn probes_match_the_solver() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let start = Grid::new(BitboardProvider::new(3, 3), Rules::default());
+        check(&Tablebase::generate(&start, 9), &start, &mut rng);
+        let mut start = Grid::new(BitboardProvider::new(3, 4), Rules::default());
+        start.set(5, CaseValue::Black);
+        check(&Tablebase::generate(&start, 6), &start, &mut rng);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let start = Grid::new(BitboardProvider::new(3, 3), Rules::default());
+        let tablebase = Tablebase::generate(&start, 5);
+        let bytes = tablebase.to_bytes();
+        let loaded = Tablebase::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.to_bytes(), bytes);
+        assert!(Tablebase::from_bytes(&bytes[1..]).is_err());
+    }
+}
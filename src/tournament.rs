@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+
+use rand::Rng;
+
+use crate::{
+    grid::{Grid, GridProvider},
+    player::{play_match, random_openings, CompareResult, Player},
+};
+
+// Results of every tournament, the ratings are fitted again on all of them since the Elo of
+// separate runs are centred on their own players and can't be compared
+const RESULTS_FILE: &str = "results.json";
+
+// Each pairing that played also gets one virtual draw, like the BayesElo prior, so a player that
+// won every game still has a finite rating
+const PRIOR_DRAWS: f64 = 1.;
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct Rating {
+    pub elo: f64,
+    // Half width of the 95% confidence interval
+    pub interval: f64,
+    pub games: usize,
+}
+
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct Tournament {
+    pub names: Vec<String>,
+    // `results[i][j]` is the result of `i` against `j`
+    pub results: Vec<Vec<CompareResult>>,
+}
+
+impl Tournament {
+    // Round robin where every pair plays `games` games, swapping colours on the same openings
+    pub fn run<T: GridProvider + Clone>(
+        start: &Grid<T>,
+        entrants: &mut [(String, Box<dyn Player<T>>)],
+        games: usize,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let openings = random_openings(start, (games + 1) / 2, 2, rng);
+        let mut results = (0..entrants.len())
+            .map(|_| {
+                (0..entrants.len())
+                    .map(|_| CompareResult::default())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        for j in 1..entrants.len() {
+            let (left, right) = entrants.split_at_mut(j);
+            for i in 0..j {
+                let result = play_match(start, &mut *left[i].1, &mut *right[0].1, games, &openings);
+                println!("{} - {}: {:?}", left[i].0, right[0].0, result);
+                results[j][i] = CompareResult {
+                    win: result.loose,
+                    loose: result.win,
+                    none: result.none,
+                };
+                results[i][j] = result;
+            }
+        }
+        Self {
+            names: entrants.iter().map(|x| x.0.clone()).collect(),
+            results,
+        }
+    }
+
+    // Maximum likelihood Elo ratings centred on 0
+    pub fn ratings(&self) -> Vec<Rating> {
+        let n = self.names.len();
+        let k = 10f64.ln() / 400.;
+        let expected = |a: f64, b: f64| 1. / (1. + (-(a - b) * k).exp());
+        let prior = |i: usize, j: usize| {
+            let x = &self.results[i][j];
+            if x.win + x.loose + x.none > 0 {
+                PRIOR_DRAWS
+            } else {
+                0.
+            }
+        };
+        let games = |i: usize, j: usize| {
+            let x = &self.results[i][j];
+            (x.win + x.loose + x.none) as f64 + prior(i, j)
+        };
+        let points = |i: usize, j: usize| {
+            let x = &self.results[i][j];
+            x.win as f64 + (x.none as f64 + prior(i, j)) / 2.
+        };
+        let mut elo = vec![0.; n];
+        for _ in 0..100 {
+            for i in 0..n {
+                let (gradient, curvature) = (0..n).filter(|j| *j != i).fold((0., 0.), |acc, j| {
+                    let e = expected(elo[i], elo[j]);
+                    (
+                        acc.0 + points(i, j) - games(i, j) * e,
+                        acc.1 + games(i, j) * e * (1. - e),
+                    )
+                });
+                if curvature > 0. {
+                    elo[i] += gradient / (k * curvature);
+                }
+            }
+            let mean = elo.iter().sum::<f64>() / n as f64;
+            elo.iter_mut().for_each(|x| *x -= mean);
+        }
+        (0..n)
+            .map(|i| {
+                let information = (0..n).filter(|j| *j != i).fold(0., |acc, j| {
+                    let e = expected(elo[i], elo[j]);
+                    acc + games(i, j) * e * (1. - e)
+                });
+                Rating {
+                    elo: elo[i],
+                    interval: 1.96 / (k * information.sqrt()),
+                    games: self.results[i]
+                        .iter()
+                        .map(|x| x.win + x.loose + x.none)
+                        .sum(),
+                }
+            })
+            .collect()
+    }
+
+    // Wins, losses and draws of every row player against every column player, strongest first
+    pub fn crosstable(&self) -> String {
+        let ratings = self.ratings();
+        let mut order = (0..self.names.len()).collect::<Vec<_>>();
+        order.sort_by(|a, b| ratings[*b].elo.partial_cmp(&ratings[*a].elo).unwrap());
+        let labels = order
+            .iter()
+            .enumerate()
+            .map(|(n, i)| format!("{} {}", n + 1, self.names[*i]))
+            .collect::<Vec<_>>();
+        let width = labels.iter().map(|x| x.len()).max().unwrap_or(0);
+        let mut table = format!("{:width$} {:>11}", "", "Elo", width = width);
+        for (n, _) in order.iter().enumerate() {
+            table += &format!(" {:>11}", n + 1);
+        }
+        for (label, i) in labels.iter().zip(&order) {
+            table += &format!(
+                "\n{:width$} {:>5.0} ± {:<3.0}",
+                label,
+                ratings[*i].elo,
+                ratings[*i].interval,
+                width = width
+            );
+            for j in &order {
+                let x = &self.results[*i][*j];
+                if i == j {
+                    table += &format!(" {:>11}", "-");
+                } else {
+                    table += &format!(" {:>11}", format!("{}-{}-{}", x.win, x.loose, x.none));
+                }
+            }
+        }
+        table
+    }
+
+    // Results of the previous tournaments, players are matched by name
+    pub fn load() -> Self {
+        std::fs::read_to_string(RESULTS_FILE)
+            .map(|x| serde_json::from_str(&x).unwrap())
+            .unwrap_or_default()
+    }
+
+    // Adds the results to the ones of the previous tournaments
+    pub fn save(&self) {
+        let mut saved = Self::load();
+        saved.merge(self);
+        std::fs::write(RESULTS_FILE, serde_json::to_string(&saved).unwrap()).unwrap();
+        println!("Results saved in `{}`", RESULTS_FILE);
+    }
+
+    fn merge(&mut self, other: &Self) {
+        let indexes = other
+            .names
+            .iter()
+            .map(|name| match self.names.iter().position(|x| x == name) {
+                Some(i) => i,
+                None => {
+                    self.names.push(name.clone());
+                    self.results
+                        .iter_mut()
+                        .for_each(|x| x.push(CompareResult::default()));
+                    self.results
+                        .push(vec![CompareResult::default(); self.names.len()]);
+                    self.names.len() - 1
+                }
+            })
+            .collect::<Vec<_>>();
+        for (i, row) in indexes.iter().zip(&other.results) {
+            for (j, x) in indexes.iter().zip(row) {
+                let result = &mut self.results[*i][*j];
+                result.win += x.win;
+                result.loose += x.loose;
+                result.none += x.none;
+            }
+        }
+    }
+}
+
+// Ratings fitted on the results of every saved tournament
+pub fn load_ratings() -> BTreeMap<String, Rating> {
+    let saved = Tournament::load();
+    saved.names.iter().cloned().zip(saved.ratings()).collect()
+}
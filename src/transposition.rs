@@ -0,0 +1,61 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Fixed size hash table, a new entry always replaces the previous one stored in its slot
+pub struct TranspositionTable<T> {
+    entries: Vec<Option<(u64, T)>>,
+    mask: usize,
+}
+
+impl<T> TranspositionTable<T> {
+    pub fn new(size_log2: u32) -> Self {
+        Self {
+            entries: (0..(1usize << size_log2)).map(|_| None).collect(),
+            mask: (1 << size_log2) - 1,
+        }
+    }
+
+    pub fn get(&self, hash: u64) -> Option<&T> {
+        match &self.entries[hash as usize & self.mask] {
+            Some((h, value)) if *h == hash => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&mut self, hash: u64, value: T) {
+        self.entries[hash as usize & self.mask] = Some((hash, value));
+    }
+}
+
+// Table shared between search threads without locks. Every entry is stored with its hash XORed
+// with its data, so an entry torn by two threads writing at once reads as a miss.
+pub struct SharedTable {
+    entries: Vec<(AtomicU64, AtomicU64)>,
+    mask: usize,
+}
+
+impl SharedTable {
+    pub fn new(size_log2: u32) -> Self {
+        Self {
+            entries: (0..(1usize << size_log2))
+                .map(|_| (AtomicU64::new(0), AtomicU64::new(0)))
+                .collect(),
+            mask: (1 << size_log2) - 1,
+        }
+    }
+
+    pub fn get(&self, hash: u64) -> Option<u64> {
+        let (key, data) = &self.entries[hash as usize & self.mask];
+        let data = data.load(Ordering::Relaxed);
+        if key.load(Ordering::Relaxed) ^ data == hash {
+            Some(data)
+        } else {
+            None
+        }
+    }
+
+    pub fn insert(&self, hash: u64, data: u64) {
+        let (key, value) = &self.entries[hash as usize & self.mask];
+        key.store(hash ^ data, Ordering::Relaxed);
+        value.store(data, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,39 @@
+use crate::grid::CaseValue;
+
+// Keys are derived from the case index instead of being stored in a table, so grids of every
+// size share them and hashes stay the same from one run to another.
+fn key(seed: u64) -> u64 {
+    // splitmix64
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// Empty cases don't have a key, which one is playable is given by `unlock_key`
+pub fn case_key(index: usize, value: CaseValue) -> u64 {
+    match value {
+        CaseValue::Red => key(index as u64 * 4),
+        CaseValue::Blue => key(index as u64 * 4 + 1),
+        CaseValue::Black => key(index as u64 * 4 + 2),
+        CaseValue::Yellow | CaseValue::White => 0,
+    }
+}
+
+pub fn unlock_key(last_play: Option<usize>, was_everything_yellow: bool) -> u64 {
+    match last_play {
+        Some(index) if !was_everything_yellow => key(index as u64 * 4 + 3),
+        _ => EVERYTHING_YELLOW_KEY,
+    }
+}
+
+pub fn turn_key(is_red_turn: bool) -> u64 {
+    if is_red_turn {
+        RED_TURN_KEY
+    } else {
+        0
+    }
+}
+
+const RED_TURN_KEY: u64 = 0x6A09_E667_F3BC_C908;
+const EVERYTHING_YELLOW_KEY: u64 = 0xBB67_AE85_84CA_A73B;